@@ -1,51 +1,106 @@
-use std::hash::{Hash, Hasher};
 use ggez::{Context, GameResult};
 use ggez::glam::Vec2;
 use ggez::graphics::{Color, DrawMode, DrawParam, Mesh};
 use ggez::mint::Point2;
-use ordered_float::OrderedFloat;
-use crate::constants::{MAX_BOID_VELOCITY, MIN_BOID_VELOCITY, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::color::Rgba;
+use crate::config::EdgeBehavior;
+use crate::constants::{BOID_COLORS, MAX_BOID_VELOCITY, MIN_BOID_VELOCITY};
+use std::ops::RangeInclusive;
 
+#[derive(Clone)]
 pub struct Boid {
     pos: Vec2,
     speed: Vec2,
-    color: Color
+    // Index of the boid's color in BOID_COLORS (which is where its color comes from):
+    species: usize,
+    // Number of steps since the boid was created:
+    age: u32,
+    // What the boid's maximum velocity is multiplied by (more than 1 while it sprints):
+    max_speed_scale: f32,
+    // The direction the boid faces when drawn (of length 1), which eases towards its velocity:
+    render_heading: Vec2
 }
 
 impl Boid {
-    pub fn new(initial_x: f32, initial_y: f32, color: Color) -> Self {
+    pub fn new(initial_x: f32, initial_y: f32, initial_speed: Vec2, species: usize) -> Self {
         Self {
             pos: Vec2::new(initial_x, initial_y),
-            speed: Vec2::ONE * MAX_BOID_VELOCITY / 2.,
-            color
+            speed: initial_speed,
+            species,
+            age: 0,
+            max_speed_scale: 1.,
+            render_heading: initial_speed.try_normalize().unwrap_or(Vec2::X)
         }
     }
 
-    pub fn go_forward(&mut self) {
-        self.pos += self.speed;
+    /// Moves the boid by its speed (times the given time scale, which is 1 for a whole step) inside an
+    /// area of the given size, with the edges of the area (top, right, bottom, left) acting according
+    /// to their behavior when the boid crosses them.
+    /// Since this happens once every step, the boid also gets one step older.
+    /// Returns whether the boid is still in the area, i.e. it didn't leave through an open edge.
+    pub fn go_forward(&mut self, bounds: Vec2, edges: [EdgeBehavior; 4], time_scale: f32) -> bool {
+        self.pos += time_scale * self.speed;
+        self.age = self.age.saturating_add(1);
 
-        // Fix position on screen:
-        self.pos.x = self.pos.x.rem_euclid(SCREEN_WIDTH);
-        self.pos.y = self.pos.y.rem_euclid(SCREEN_HEIGHT);
+        let [top, right, bottom, left] = edges;
+        let stays = cross_edge(&mut self.pos.x, &mut self.speed.x, bounds.x, left, right)
+            && cross_edge(&mut self.pos.y, &mut self.speed.y, bounds.y, top, bottom);
+        self.wrap_position(bounds);
+        stays
+    }
+
+    /// Fixes the boid's position so it's inside an area of the given size.
+    fn wrap_position(&mut self, bounds: Vec2) {
+        self.pos.x = self.pos.x.rem_euclid(bounds.x);
+        self.pos.y = self.pos.y.rem_euclid(bounds.y);
+    }
+
+    /// Moves the boid by the given offset, keeping it inside an area of the given size.
+    pub fn move_by(&mut self, offset: Vec2, bounds: Vec2) {
+        self.pos += offset;
+        self.wrap_position(bounds);
+    }
+
+    /// If the boid is inside the given circle, moves it to the circle's edge and removes the part of
+    /// its velocity that goes into the circle. Returns whether the boid was inside the circle.
+    /// The boid is kept inside an area of the given size.
+    pub fn push_out_of(&mut self, center: Vec2, radius: f32, bounds: Vec2) -> bool {
+        let offset = self.pos - center;
+        if offset.length_squared() >= radius * radius {
+            return false;
+        }
+
+        // A boid exactly at the center can be pushed in any direction:
+        let normal = offset.try_normalize().unwrap_or(Vec2::X);
+        self.pos = center + normal * radius;
+        self.wrap_position(bounds);
+
+        // Remove the velocity going into the circle:
+        let inwards = self.speed.dot(normal).min(0.);
+        self.speed -= inwards * normal;
+        true
     }
 
     /// All boids are drawn in the same shape (rotated to match their path of course). This method
-    /// returns that shape as a Mesh for rendering. The mesh is not located anywhere in particular
-    /// and upon rendering it using DrawParams will be necessary to move it to the desired location.
-    pub fn get_boid_mesh(context: &Context) -> GameResult<Mesh> {
-        // Get the triangles vertexes:
+    /// returns the vertexes of that shape, a triangle pointing to the right around (0, 0).
+    pub fn get_boid_shape() -> [Point2<f32>; 3] {
         const SHAPE_STRETCHER: f32 = 5.;
-        let mesh_points = vec![
+        [
             Point2 { x: -SHAPE_STRETCHER, y: SHAPE_STRETCHER },
             Point2 { x: -SHAPE_STRETCHER, y: -SHAPE_STRETCHER },
             Point2 { x: 1.5 * SHAPE_STRETCHER, y: 0. }
-        ];
+        ]
+    }
 
+    /// Returns the boids' shape as a Mesh for rendering. The mesh is not located anywhere in
+    /// particular and upon rendering it using DrawParams will be necessary to move it to the
+    /// desired location.
+    pub fn get_boid_mesh(context: &Context) -> GameResult<Mesh> {
         // Create the mesh:
         Mesh::new_polygon(
             context,
             DrawMode::fill(),
-            &mesh_points,
+            &Self::get_boid_shape(),
             Color::WHITE
         )
     }
@@ -53,8 +108,25 @@ impl Boid {
     pub fn get_draw_param(&self) -> DrawParam {
         DrawParam::new()
             .dest(self.pos)
-            .rotation(-self.speed.angle_between(Vec2::X))
-            .color(self.color)
+            .rotation(self.rotation())
+            .color(Color::from(self.color()))
+    }
+
+    /// Returns the angle (in radians, clockwise) the boid's shape is rotated by when drawn.
+    pub fn rotation(&self) -> f32 {
+        -self.render_heading.angle_between(Vec2::X)
+    }
+
+    /// Eases the direction the boid faces when drawn towards its velocity, keeping the given share
+    /// of the previous direction. The directions are mixed as vectors rather than as angles, so
+    /// turning across the angles' wraparound doesn't spin the boid the long way around.
+    pub fn smooth_heading(&mut self, smoothing: f32) {
+        let Some(heading) = self.speed.try_normalize() else {
+            return;
+        };
+        self.render_heading = self.render_heading.lerp(heading, 1. - smoothing)
+            .try_normalize()
+            .unwrap_or(heading);
     }
     pub fn pos(&self) -> Vec2 {
         self.pos
@@ -62,53 +134,181 @@ impl Boid {
     pub fn speed(&self) -> Vec2 {
         self.speed
     }
-    pub fn color(&self) -> Color {
-        self.color
+    pub fn color(&self) -> Rgba {
+        BOID_COLORS[self.species]
+    }
+    pub fn species(&self) -> usize {
+        self.species
+    }
+    pub fn age(&self) -> u32 {
+        self.age
+    }
+    pub fn set_age(&mut self, age: u32) {
+        self.age = age;
+    }
+
+    /// Changes the boid's species (and therefore its color).
+    pub fn set_species(&mut self, species: usize) {
+        self.species = species;
+    }
+
+    /// Multiplies the boid's maximum velocity by the given scale (e.g: while it sprints), and by 1
+    /// to restore it.
+    pub fn set_max_speed_scale(&mut self, scale: f32) {
+        self.max_speed_scale = scale;
     }
 
     pub fn add_dir(&mut self, direction: Vec2) {
         self.speed += direction;
-        // Limit speed:
-        const MAX_SPEED: Vec2 = Vec2::new(MAX_BOID_VELOCITY, MAX_BOID_VELOCITY);
-        self.speed = self.speed.clamp(-MAX_SPEED, MAX_SPEED);
+        self.limit_speed();
+    }
 
-        if self.speed.length() < MIN_BOID_VELOCITY {
-            self.speed = MIN_BOID_VELOCITY * self.speed.normalize_or_zero();
+    /// Sets the boid's velocity directly (instead of steering it like `add_dir`), within the same
+    /// limits.
+    pub fn set_speed(&mut self, speed: Vec2) {
+        self.speed = speed;
+        self.limit_speed();
+    }
+
+    /// Keeps the boid's speed between the minimum and maximum velocities (see `speed_limits`).
+    fn limit_speed(&mut self) {
+        let limits = speed_limits(self.max_speed_scale);
+        let (min_speed, max_speed) = (*limits.start(), *limits.end());
+
+        // Without any room between the limits, the boid goes at exactly that speed:
+        if min_speed == max_speed {
+            self.speed = max_speed * self.speed.normalize_or_zero();
+            return;
+        }
+
+        self.speed = self.speed.clamp(Vec2::splat(-max_speed), Vec2::splat(max_speed));
+        if self.speed.length() < min_speed {
+            self.speed = min_speed * self.speed.normalize_or_zero();
         }
     }
 }
 
-// Implementations necessary for being used as hashmap keys:
-impl PartialEq for Boid {
-    fn eq(&self, other: &Self) -> bool {
-        self.pos == other.pos && self.speed == other.speed
-    }
+/// Returns the range a boid's velocity is kept in, given the scale of its maximum velocity (e.g:
+/// while it sprints). The maximum applies to each axis, and the minimum to the whole velocity. A
+/// minimum above the scaled maximum gives way to it, so the two limits never fight each other.
+pub fn speed_limits(max_speed_scale: f32) -> RangeInclusive<f32> {
+    let max_speed = max_speed_scale * MAX_BOID_VELOCITY;
+    MIN_BOID_VELOCITY.min(max_speed)..=max_speed
 }
-impl Eq for Boid {}
 
-impl Hash for Boid {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        // Convert to OrderedFloat for hashing:
-        let (pos_x, pos_y) = (OrderedFloat(self.pos.x), OrderedFloat(self.pos.y));
-        let (dir_x, dir_y) = (OrderedFloat(self.speed.x), OrderedFloat(self.speed.y));
+/// Applies the behavior of the edge a boid crossed on a single axis (given its position and speed
+/// on that axis, the size of the area on it and the behavior of its low and high edges). Wrapping
+/// is left to the caller. Returns false if the boid left through an open edge.
+fn cross_edge(
+    pos: &mut f32,
+    speed: &mut f32,
+    size: f32,
+    low: EdgeBehavior,
+    high: EdgeBehavior
+) -> bool {
+    let (edge, wall) = if *pos < 0. {
+        (low, 0.)
+    } else if *pos >= size {
+        (high, size)
+    } else {
+        return true;
+    };
 
-        // Hash:
-        pos_x.hash(state);
-        pos_y.hash(state);
-        dir_x.hash(state);
-        dir_y.hash(state);
+    match edge {
+        EdgeBehavior::Open => false,
+        EdgeBehavior::Reflect => {
+            // Mirror the boid's position and speed by the edge:
+            *pos = 2. * wall - *pos;
+            *speed = -*speed;
+            true
+        }
+        EdgeBehavior::Wrap | EdgeBehavior::Evade => true
     }
 }
 
 // To make distance calculations more efficient, the boids will be located in a grid where each cell
 // holds all boids within a certain distance. This struct saves the boid and its location in the
 // grid:
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone)]
 pub struct GridBoid {
     // Boid itself:
     pub boid: Boid,
 
     // Coordinates in grid:
     pub row: usize,
-    pub col: usize
+    pub col: usize,
+
+    // Identifier of the boid, which never changes and isn't given to any other boid in the same
+    // simulation (unlike its index, which changes when other boids are removed):
+    pub id: u64,
+
+    // Identifiers of the neighbors the boid remembers, and the steps left until it forgets each of
+    // them (only used with neighbor memory):
+    pub memory: Vec<(u64, u32)>,
+
+    // How panicked the boid is (from 0 to 1) and the direction it flees in because of it (only
+    // used with panic propagation):
+    pub panic: f32,
+    pub panic_dir: Vec2
+}
+
+/// A read-only snapshot of a boid, for code that reads the flock without depending on how the
+/// simulation stores its boids (e.g: exporters).
+#[derive(Clone, Copy)]
+pub struct BoidView {
+    // The boid's identifier, which stays the same for the boid's whole life:
+    pub id: u64,
+    pub pos: Vec2,
+    pub velocity: Vec2,
+    // The angle the boid faces when drawn (in radians, clockwise from the right), which follows its
+    // velocity (see HEADING_SMOOTHING):
+    pub heading: f32,
+    // Index of the boid's color in BOID_COLORS:
+    pub species_index: usize
+}
+
+impl BoidView {
+    pub fn new(grid_boid: &GridBoid) -> Self {
+        let boid = &grid_boid.boid;
+        Self {
+            id: grid_boid.id,
+            pos: boid.pos(),
+            velocity: boid.speed(),
+            heading: boid.rotation(),
+            species_index: boid.species()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_limits_meet_below_the_minimum() {
+        assert_eq!(speed_limits(1.), MIN_BOID_VELOCITY..=MAX_BOID_VELOCITY);
+
+        // A maximum scaled below the minimum takes the minimum down with it:
+        let scale = 0.5 * MIN_BOID_VELOCITY / MAX_BOID_VELOCITY;
+        let speed = scale * MAX_BOID_VELOCITY;
+        assert_eq!(speed_limits(scale), speed..=speed);
+    }
+
+    #[test]
+    fn boids_with_meeting_limits_go_at_exactly_that_speed() {
+        // With the maximum scaled down to the minimum, there's no room between the limits (boids at
+        // rest have no heading to speed up in, so every boid starts moving):
+        let scale = MIN_BOID_VELOCITY / MAX_BOID_VELOCITY;
+        let speed = *speed_limits(scale).end();
+        for i in 0..100 {
+            let angle = i as f32 * 0.37;
+            let mut boid = Boid::new(0., 0., Vec2::from_angle(angle) * (1 + i % 7) as f32, 0);
+            boid.set_max_speed_scale(scale);
+            for step in 0..20 {
+                boid.add_dir(Vec2::from_angle(angle * step as f32) * step as f32);
+                let error = (boid.speed().length() - speed).abs();
+                assert!(error <= speed * 4. * f32::EPSILON, "speed {}", boid.speed().length());
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use crate::constants::CAMERA_STIFFNESS;
+use ggez::glam::Vec2;
+use ggez::graphics::Rect;
+
+/// The part of the simulation that is shown on the screen. The camera looks at a point in the
+/// simulation and can zoom into it, but never shows anything outside the simulation's borders
+/// (unless the entire simulation is shown and doesn't fill the screen).
+pub struct Camera {
+    // The size of the simulation, and the size of the area on the screen it's drawn in (which is
+    // also the size of the view when it isn't zoomed):
+    size: Vec2,
+    screen_size: Vec2,
+
+    // The point at the center of the view:
+    center: Vec2,
+    // How much the view is magnified (1 shows the simulation in its actual size):
+    zoom: f32,
+
+    // Velocities of the center and the zoom, used to smoothly follow a point:
+    center_velocity: Vec2,
+    zoom_velocity: f32,
+}
+
+impl Camera {
+    /// Creates a camera showing the entire simulation, given the simulation's size and the size of
+    /// the area on the screen it's drawn in.
+    pub fn new(size: Vec2, screen_size: Vec2) -> Self {
+        let mut camera = Self {
+            size,
+            screen_size,
+            center: size / 2.,
+            zoom: 1.,
+            center_velocity: Vec2::ZERO,
+            zoom_velocity: 0.,
+        };
+        camera.zoom = camera.full_zoom();
+        camera
+    }
+
+    /// Returns the zoom that shows the entire simulation (which is 1 when the simulation is as large
+    /// as its area on the screen, and lower when it's larger).
+    pub fn full_zoom(&self) -> f32 {
+        (self.screen_size / self.size).min_element()
+    }
+
+    /// Moves the camera towards the given center and zoom over `dt` seconds. The camera follows
+    /// them like a critically damped spring, so it eases towards them without overshooting.
+    pub fn follow(&mut self, center: Vec2, zoom: f32, dt: f32) {
+        // Acceleration of a critically damped spring is w^2 * (target - x) - 2 * w * v:
+        let center_acc = CAMERA_STIFFNESS * CAMERA_STIFFNESS * (center - self.center)
+            - 2. * CAMERA_STIFFNESS * self.center_velocity;
+        let zoom_acc = CAMERA_STIFFNESS * CAMERA_STIFFNESS * (zoom - self.zoom)
+            - 2. * CAMERA_STIFFNESS * self.zoom_velocity;
+
+        self.center_velocity += center_acc * dt;
+        self.zoom_velocity += zoom_acc * dt;
+        self.center += self.center_velocity * dt;
+        self.zoom = (self.zoom + self.zoom_velocity * dt).max(self.full_zoom());
+
+        // Keep the view inside the simulation (centering it on axes where it's larger than the
+        // simulation):
+        let half_size = self.view_size() / 2.;
+        let max_center = (self.size - half_size).max(self.size / 2.);
+        let min_center = half_size.min(self.size / 2.);
+        self.center = self.center.clamp(min_center, max_center);
+    }
+
+    /// Returns the size of the visible part of the simulation.
+    fn view_size(&self) -> Vec2 {
+        self.screen_size / self.zoom
+    }
+
+    /// Returns the visible part of the simulation, in simulation coordinates.
+    pub fn view_rect(&self) -> Rect {
+        let size = self.view_size();
+        let top_left = self.center - size / 2.;
+        Rect::new(top_left.x, top_left.y, size.x, size.y)
+    }
+
+    /// Converts a point on the screen (e.g: the mouse's position) to simulation coordinates. The
+    /// point is relative to the top left corner of the area the simulation is drawn in.
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        let rect = self.view_rect();
+        Vec2::new(rect.x, rect.y) + point / self.zoom
+    }
+}
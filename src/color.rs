@@ -0,0 +1,43 @@
+use ggez::graphics::Color;
+
+/// A color in the simulation, with each channel between 0 and 1. The simulation only uses this type
+/// and it is converted to the renderer's color type when drawing.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rgba {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Rgba {
+    pub const BLACK: Rgba = Rgba::new(0., 0., 0., 1.);
+    pub const WHITE: Rgba = Rgba::new(1., 1., 1., 1.);
+    pub const RED: Rgba = Rgba::new(1., 0., 0., 1.);
+    pub const GREEN: Rgba = Rgba::new(0., 1., 0., 1.);
+    pub const BLUE: Rgba = Rgba::new(0., 0., 1., 1.);
+    pub const YELLOW: Rgba = Rgba::new(1., 1., 0., 1.);
+    pub const MAGENTA: Rgba = Rgba::new(1., 0., 1., 1.);
+    pub const CYAN: Rgba = Rgba::new(0., 1., 1., 1.);
+
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Returns the color `t` of the way from this color to the other one (0 returns this color and
+    /// 1 returns the other).
+    pub fn lerp(self, other: Rgba, t: f32) -> Rgba {
+        Rgba::new(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+}
+
+impl From<Rgba> for Color {
+    fn from(color: Rgba) -> Self {
+        Color::new(color.r, color.g, color.b, color.a)
+    }
+}
@@ -0,0 +1,961 @@
+use crate::boid::{speed_limits, Boid};
+use crate::constants::*;
+use crate::stats::RULE_COUNT;
+use crate::toml_text::to_toml_lines;
+use ggez::glam::Vec2;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+
+/// Parameters of the simulation that can be changed without recompiling. The default values are
+/// taken from `constants.rs`, and can be overridden by a TOML file (fields missing from the file
+/// keep their default values).
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    // Maximum magnitude of the combined steering applied to a boid in a single step:
+    pub max_force: f32,
+
+    // Factor of the pull towards the center of the screen:
+    pub center_pull_factor: f32,
+
+    // Magnitude of the random steering added to every boid:
+    pub noise_factor: f32,
+
+    // Average speed above which the flock is slowed down, and the share of velocity lost in every
+    // step while it is (see CRUISE_SPEED):
+    pub cruise_speed: f32,
+    pub speed_damping: f32,
+
+    // Trail left by every boid in every step, the share of the trails lost in every step, and how
+    // much boids follow trails:
+    pub trail_deposit: f32,
+    pub trail_decay: f32,
+    pub trail_follow_factor: f32,
+
+    // Seed of the simulation's randomness (random if not given):
+    pub seed: Option<u64>,
+
+    // How the separation push depends on the distance between boids, and which cells separation
+    // looks for close boids in:
+    pub separation_curve: SeparationCurve,
+    pub separation_scan: ScanMode,
+
+    // How boids keep their distance from each other:
+    pub spacing_model: SpacingModel,
+
+    // Whether separation pushes less from close boids ahead going the same way, and how much less:
+    pub drafting: bool,
+    pub drafting_allowance: f32,
+
+    // Distance boids are pushed apart to when they get closer than it (zero disables it):
+    pub min_distance: f32,
+
+    // What the alignment rule averages:
+    pub alignment_mode: AlignmentMode,
+
+    // Whether close boids are weighted by their speed in alignment and cohesion:
+    pub speed_weighted_influence: bool,
+
+    // Whether cohesion steers towards the close boids' predicted positions, and how many steps
+    // ahead they're predicted:
+    pub predictive_cohesion: bool,
+    pub cohesion_lookahead: f32,
+
+    // Number of steps boids keep flocking with neighbors of their species that went out of range
+    // (zero disables it):
+    pub neighbor_memory: u32,
+
+    // Whether the target pulls the boids closest to it more (see FRONT_LED_TARGET):
+    pub front_led_target: bool,
+
+    // Share of the initial boids each species gets, see SPECIES_PROPORTIONS:
+    pub species_proportions: Vec<f32>,
+
+    // Which way spawned boids head:
+    pub spawn_heading: SpawnHeading,
+
+    // Whether colors matter in alignment and cohesion, and the relationships between species (both
+    // see COLOR_AFFECTS_FLOCKING):
+    pub color_affects_flocking: bool,
+    pub species_relationships: Vec<Vec<f32>>,
+
+    // How much of their steering boids inside calm zones get:
+    pub calm_factor: f32,
+
+    // The radius of the time lens, and how much it slows down the boids inside it (see
+    // TIME_LENS_FACTOR):
+    pub time_lens_radius: f32,
+    pub time_lens_factor: f32,
+
+    // How strongly reefs pull the boids inside them, and how much they slow them down (both see
+    // REEF_PULL):
+    pub reef_pull: f32,
+    pub reef_damping: f32,
+
+    // Acceleration applied to every boid in every step (x, y):
+    pub gravity: [f32; 2],
+
+    // What each edge does to boids reaching it (top, right, bottom, left):
+    pub edges: [EdgeBehavior; 4],
+
+    // The corners of a polygon the boids are confined to while walls are restricted (if given):
+    pub boundary_polygon: Option<Vec<[f32; 2]>>,
+
+    // How boids notice walls, and the number and length of the whiskers (if they're used):
+    pub wall_perception: WallPerception,
+    // How hard boids inside the margin evade walls (with margin perception):
+    pub wall_evasion: WallEvasion,
+    pub whisker_count: usize,
+    pub whisker_length: f32,
+
+    // Strength of gusts (relative to the length of the swipe), and the distance from the swipe up
+    // to which they push boids:
+    pub gust_strength: f32,
+    pub gust_radius: f32,
+
+    // How much the excitement level scales the maximum velocity and the separation, and the curve
+    // of the scaling (see EXCITEMENT_SPEED):
+    pub excitement_speed: f32,
+    pub excitement_separation: f32,
+    pub excitement_curve: f32,
+
+    // Field of view (in degrees) and detection range of predators:
+    pub predator_fov: f32,
+    pub predator_detection_range: f32,
+
+    // Number of steps predators are sated for after catching a boid:
+    pub predator_satiation: u32,
+
+    // How much of their neighbors' panic boids catch, and how fast it fades (both see
+    // PANIC_TRANSMISSION):
+    pub panic_transmission: f32,
+    pub panic_decay: f32,
+
+    // Maximum number of boids, and which boids are evicted when spawning more:
+    pub max_boids: usize,
+    pub eviction_policy: EvictionPolicy,
+
+    // Boids flowing into the simulation through an edge (none if not given):
+    pub inflow: Option<InflowSpec>,
+
+    // Whether touching flocks of different colors merge, and after how many steps of contact:
+    pub merge_on_contact: bool,
+    pub merge_contact_steps: u32,
+
+    // Number of steps the point boids follow takes to move to a new leader:
+    pub leader_handoff_steps: u32,
+
+    // Who the boids follow, and the smallest group that gets a leader of its own (see LEADER_MODE):
+    pub leader_mode: LeaderMode,
+    pub leader_min_group_size: usize,
+
+    // Whether the simulation pauses while the window is out of focus:
+    pub pause_when_unfocused: bool,
+
+    // Whether the window waits for the display's refresh (only read on startup), and the maximum
+    // number of frames per second without it (if given):
+    pub vsync: bool,
+    pub fps_cap: Option<u32>,
+
+    // Whether the session is saved when the window is closed, and loaded on startup:
+    pub save_session_on_quit: bool,
+    pub load_last_session: bool,
+
+    // Number of steps between recorded statistics, and the distance under which boids are
+    // considered connected when splitting them into groups:
+    pub stats_interval: u64,
+    pub group_distance: f32,
+
+    // Number of snapshots kept in the timeline, and the number of steps between snapshots:
+    pub history_length: usize,
+    pub history_stride: u64,
+
+    // Whether all boids are drawn in a single instanced draw call:
+    pub instanced_rendering: bool,
+
+    // Whether the boids are drawn in batches of a single species each:
+    pub group_draw_by_species: bool,
+
+    // The order the boids are drawn in (within their batch):
+    pub sort_mode: SortMode,
+
+    // How much of its drawn heading a boid keeps in every step (0 draws it facing its velocity):
+    pub heading_smoothing: f32,
+
+    // Whether the day cycle starts on, and how long a full day takes (in seconds):
+    pub day_cycle: bool,
+    pub day_cycle_period: f32,
+
+    // Number of columns and rows of independent simulations the window is split into:
+    pub region_columns: usize,
+    pub region_rows: usize,
+
+    // Size of the world of every region (see WORLD_SIZE):
+    pub world_size: Option<[f32; 2]>,
+
+    // Number of rings of cells searched around a boid's grid cell:
+    pub neighbor_rings: usize,
+
+    // Whether the grid's neighbor search is verified against checking every pair of boids:
+    pub verify_neighbors: bool,
+
+    // Whether the rules are calculated in parallel threads:
+    pub parallel: bool,
+    // Number of threads the rules are split between (by the available parallelism if not given):
+    pub threads: Option<usize>,
+    // Every how many steps each rule is recalculated (see RULE_INTERVALS):
+    pub rule_intervals: [u64; RULE_COUNT - 1],
+
+    // Maximum amount of simulation steps in a single frame, steps beyond it are dropped:
+    pub max_steps_per_frame: u32,
+
+    // Whether crowded grid cells should be sampled instead of fully scanned:
+    pub reduced_accuracy: bool,
+
+    // Number of boids in a cell above which it is considered crowded:
+    pub density_threshold: usize,
+
+    // Number of boids sampled from a crowded cell:
+    pub neighbor_samples: usize,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            max_force: MAX_BOID_FORCE,
+            center_pull_factor: CENTER_PULL_FACTOR,
+            noise_factor: NOISE_FACTOR,
+            cruise_speed: CRUISE_SPEED,
+            speed_damping: SPEED_DAMPING,
+            trail_deposit: TRAIL_DEPOSIT,
+            trail_decay: TRAIL_DECAY,
+            trail_follow_factor: TRAIL_FOLLOW_FACTOR,
+            seed: SEED,
+            separation_curve: SEPARATION_CURVE,
+            separation_scan: SEPARATION_SCAN,
+            spacing_model: SPACING_MODEL,
+            drafting: DRAFTING,
+            drafting_allowance: DRAFTING_ALLOWANCE,
+            min_distance: MIN_BOID_DISTANCE,
+            alignment_mode: ALIGNMENT_MODE,
+            speed_weighted_influence: SPEED_WEIGHTED_INFLUENCE,
+            predictive_cohesion: PREDICTIVE_COHESION,
+            cohesion_lookahead: COHESION_LOOKAHEAD,
+            neighbor_memory: NEIGHBOR_MEMORY,
+            front_led_target: FRONT_LED_TARGET,
+            species_proportions: SPECIES_PROPORTIONS.to_vec(),
+            spawn_heading: SPAWN_HEADING,
+            color_affects_flocking: COLOR_AFFECTS_FLOCKING,
+            species_relationships: SPECIES_RELATIONSHIPS
+                .iter()
+                .map(|row| row.to_vec())
+                .collect(),
+            calm_factor: CALM_FACTOR,
+            time_lens_radius: TIME_LENS_RADIUS,
+            time_lens_factor: TIME_LENS_FACTOR,
+            reef_pull: REEF_PULL,
+            reef_damping: REEF_DAMPING,
+            gravity: GRAVITY,
+            edges: EDGES,
+            boundary_polygon: BOUNDARY_POLYGON.map(<[_]>::to_vec),
+            wall_perception: WALL_PERCEPTION,
+            wall_evasion: WALL_EVASION,
+            whisker_count: WHISKER_COUNT,
+            whisker_length: WHISKER_LENGTH,
+            gust_strength: GUST_STRENGTH,
+            gust_radius: GUST_RADIUS,
+            excitement_speed: EXCITEMENT_SPEED,
+            excitement_separation: EXCITEMENT_SEPARATION,
+            excitement_curve: EXCITEMENT_CURVE,
+            predator_fov: PREDATOR_FOV,
+            predator_detection_range: PREDATOR_DETECTION_RANGE,
+            predator_satiation: PREDATOR_SATIATION,
+            panic_transmission: PANIC_TRANSMISSION,
+            panic_decay: PANIC_DECAY,
+            max_boids: MAX_BOIDS,
+            inflow: INFLOW,
+            eviction_policy: EVICTION_POLICY,
+            merge_on_contact: MERGE_ON_CONTACT,
+            merge_contact_steps: MERGE_CONTACT_STEPS,
+            leader_handoff_steps: LEADER_HANDOFF_STEPS,
+            leader_mode: LEADER_MODE,
+            leader_min_group_size: LEADER_MIN_GROUP_SIZE,
+            pause_when_unfocused: PAUSE_WHEN_UNFOCUSED,
+            vsync: VSYNC,
+            fps_cap: FPS_CAP,
+            save_session_on_quit: SAVE_SESSION_ON_QUIT,
+            load_last_session: LOAD_LAST_SESSION,
+            stats_interval: STATS_INTERVAL,
+            group_distance: GROUP_DISTANCE,
+            history_length: HISTORY_LENGTH,
+            history_stride: HISTORY_STRIDE,
+            instanced_rendering: INSTANCED_RENDERING,
+            group_draw_by_species: GROUP_DRAW_BY_SPECIES,
+            sort_mode: SORT_MODE,
+            heading_smoothing: HEADING_SMOOTHING,
+            day_cycle: DAY_CYCLE,
+            day_cycle_period: DAY_CYCLE_PERIOD,
+            region_columns: REGION_COLUMNS,
+            region_rows: REGION_ROWS,
+            world_size: WORLD_SIZE,
+            neighbor_rings: NEIGHBOR_RINGS,
+            verify_neighbors: VERIFY_NEIGHBORS,
+            parallel: PARALLEL,
+            threads: THREADS,
+            rule_intervals: RULE_INTERVALS,
+            max_steps_per_frame: MAX_STEPS_PER_FRAME,
+            reduced_accuracy: REDUCED_ACCURACY,
+            density_threshold: DENSITY_THRESHOLD,
+            neighbor_samples: NEIGHBOR_SAMPLES,
+        }
+    }
+}
+
+/// How the push a boid gets from a single close boid in the separation rule depends on the distance
+/// between them. All curves push the same at `STEERING_DISTANCE`, and none pushes more than
+/// `MAX_SEPARATION_PUSH`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum SeparationCurve {
+    // The push grows with the distance (the classic separation rule):
+    Linear,
+    // The push is the same at any distance:
+    Inverse,
+    // The push grows as the boids get closer:
+    InverseSquare,
+}
+
+impl SeparationCurve {
+    /// Returns the contribution of a close boid to the separation rule, given the offset from the
+    /// current boid to it. A zero offset gives no push, since there's no direction to push in, but
+    /// the simulation never passes one: coincident boids are given a direction by their index first.
+    pub fn apply(self, offset: Vec2) -> Vec2 {
+        let distance = offset.length();
+        let push = match self {
+            SeparationCurve::Linear => distance,
+            SeparationCurve::Inverse => STEERING_DISTANCE,
+            // Dividing by a zero distance gives infinity, which the maximum push limits anyway:
+            SeparationCurve::InverseSquare => STEERING_DISTANCE_SQUARED / distance,
+        };
+        push.min(MAX_SEPARATION_PUSH) * offset.normalize_or_zero()
+    }
+}
+
+/// Which grid cells a rule looks for close boids in.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum ScanMode {
+    // The boid's cell and the rings of cells around it (see `neighbor_rings`):
+    Full,
+    // Only the boid's own cell, which is faster but misses close boids across the cell's border:
+    SameCellOnly,
+}
+
+impl ScanMode {
+    /// Returns the number of rings of cells around a boid's cell that are scanned, given the
+    /// number of rings a full scan covers.
+    pub fn rings(self, neighbor_rings: usize) -> usize {
+        match self {
+            ScanMode::Full => neighbor_rings,
+            ScanMode::SameCellOnly => 0,
+        }
+    }
+}
+
+/// How boids keep their distance from each other.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum SpacingModel {
+    // Separation pushes close boids away and cohesion pulls boids towards their neighbors, each
+    // with its own factor (the classic rules):
+    SeparateCohere,
+    // A single force between every two boids up to INFLUENCE_DISTANCE apart, which pushes them
+    // apart below the comfort distance and pulls them together above it:
+    Potential { comfort: f32, strength: f32 },
+}
+
+impl SpacingModel {
+    /// Returns the force between two boids at the given distance, along the offset between them. A
+    /// negative force pushes them apart and a positive one pulls them together. The force is zero
+    /// at the comfort distance and at `INFLUENCE_DISTANCE`, and pulls hardest halfway between them.
+    /// Below the comfort distance the push grows steeply (like the Lennard-Jones potential), so
+    /// crowded boids can't be squeezed together by the pulls of the rest of the flock. The classic
+    /// rules don't use it, so it's always zero for them.
+    pub fn force(self, distance: f32) -> f32 {
+        match self {
+            SpacingModel::SeparateCohere => 0.,
+            SpacingModel::Potential { comfort, strength } => {
+                let force = strength * (distance - comfort) * (INFLUENCE_DISTANCE - distance)
+                    / (INFLUENCE_DISTANCE - comfort);
+                if distance < comfort {
+                    // Boids closer than a unit push as if they were a unit apart, so the push stays
+                    // bounded:
+                    force * (comfort / distance.max(1.)).powi(2)
+                } else {
+                    force
+                }
+            }
+        }
+    }
+}
+
+/// Which way boids head when they're spawned.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum SpawnHeading {
+    // Every boid heads in its own random direction:
+    Random,
+    // All boids head the same way, at the given angle (in degrees, clockwise from the right):
+    Fixed(f32),
+    // Every boid heads away from the center it was spawned around, as if the boids burst out of it:
+    Outward,
+}
+
+impl SpawnHeading {
+    /// Returns the direction (of length 1) a boid spawned at the given offset from the center it
+    /// was spawned around heads in. Boids right at the center head in a random direction, since
+    /// there's no way out of it.
+    pub fn direction(self, offset: Vec2, rng: &mut StdRng) -> Vec2 {
+        match self {
+            SpawnHeading::Fixed(angle) => Vec2::from_angle(angle.to_radians()),
+            SpawnHeading::Outward if offset != Vec2::ZERO => offset.normalize(),
+            _ => Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)),
+        }
+    }
+}
+
+/// Who the boids follow while leaders are on.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LeaderMode {
+    // All boids follow a single leader:
+    Single,
+    // Every group of boids follows its own leader, the boid at its front:
+    PerGroup,
+}
+
+/// What the alignment rule averages over the close boids.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum AlignmentMode {
+    // The boids' velocities, so faster boids have more influence (the classic alignment rule):
+    VelocityAverage,
+    // The boids' headings, so boids only align their direction regardless of how fast they are:
+    HeadingAverage,
+}
+
+impl AlignmentMode {
+    /// Returns the part of a boid's velocity that is averaged by the alignment rule.
+    pub fn direction(self, speed: Vec2) -> Vec2 {
+        match self {
+            AlignmentMode::VelocityAverage => speed,
+            AlignmentMode::HeadingAverage => speed.normalize_or_zero(),
+        }
+    }
+}
+
+/// What an edge of the simulation's area does to boids reaching it.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EdgeBehavior {
+    // Boids crossing the edge come back from the opposite edge:
+    Wrap,
+    // Boids bounce off the edge:
+    Reflect,
+    // Boids steer away from the edge while walls are restricted, and wrap otherwise:
+    Evade,
+    // Boids crossing the edge leave the simulation:
+    Open,
+}
+
+/// How boids notice the walls they evade.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum WallPerception {
+    // Boids evade walls when they're inside the margin along them, wherever they're going:
+    Margin,
+    // Boids evade walls their whiskers (rays cast ahead of them) hit, so they react earlier but only
+    // when going towards a wall:
+    Whiskers,
+}
+
+/// How hard boids inside the margin along a wall evade it.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum WallEvasion {
+    // Always by the evasion factor, however the boid is moving:
+    Constant,
+    // By the evasion factor times the boid's speed into the wall relative to the given speed, so
+    // boids speeding into a wall turn harder and boids moving along (or away from) it barely turn:
+    ByApproach(f32),
+}
+
+impl WallEvasion {
+    /// Returns how hard a boid going into a wall at the given speed (negative when it's going away
+    /// from the wall) evades it.
+    pub fn strength(self, approach: f32) -> f32 {
+        match self {
+            WallEvasion::Constant => EVASION_FACTOR,
+            WallEvasion::ByApproach(speed) => EVASION_FACTOR * approach.max(0.) / speed,
+        }
+    }
+}
+
+/// The order boids are drawn in, so it's clear which of two overlapping boids is on top (boids drawn
+/// later are drawn over earlier ones).
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum SortMode {
+    // No particular order:
+    None,
+    // Slower boids first, so faster boids are on top:
+    BySpeed,
+    // Top to bottom, so lower boids are on top (as if they're closer to the viewer):
+    ByY,
+}
+
+impl SortMode {
+    /// Returns the key a boid is sorted by, in ascending order. Without an order all boids have the
+    /// same key, so a stable sort keeps them as they are.
+    pub fn key(self, boid: &Boid) -> f32 {
+        match self {
+            SortMode::None => 0.,
+            SortMode::BySpeed => boid.speed().length_squared(),
+            SortMode::ByY => boid.pos().y,
+        }
+    }
+}
+
+/// One of the edges of the simulation's area.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+/// A stream of boids flowing into the simulation through one of its edges.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct InflowSpec {
+    // The edge boids enter through, at random positions along it:
+    pub edge: Edge,
+    // Number of boids entering in every step (e.g: 0.5 is one boid every two steps):
+    pub rate: f32,
+    // Speed of the entering boids, which go straight into the simulation:
+    pub speed: f32,
+}
+
+/// Which boids are removed when spawning boids would put the simulation over its maximum number of
+/// boids.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum EvictionPolicy {
+    // The boids that lived the longest are removed:
+    Oldest,
+    // Random boids are removed:
+    Random,
+}
+
+/// Errors that can occur while loading a configuration file.
+pub enum ConfigError {
+    // The file couldn't be read:
+    Io(std::io::Error),
+    // The file isn't a valid TOML configuration:
+    Parse(toml::de::Error),
+    // The file was parsed, but holds values the simulation can't work with:
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "couldn't read config file: {err}"),
+            ConfigError::Parse(err) => write!(f, "couldn't parse config file: {err}"),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl SimConfig {
+    /// Loads a configuration from the TOML file in the given path and validates it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let config: Self = toml::from_str(&text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns the configuration as the text of a TOML file (a parameter per line, sorted by name),
+    /// which `load` reads back. Fails if a parameter has no TOML value (e.g: a seed above TOML's largest integer).
+    pub fn to_toml(&self) -> io::Result<String> {
+        // TOML's own serializer can't write enum variants holding values (e.g: a wall evasion by
+        // approach), so the parameters are written by to_toml_lines instead:
+        to_toml_lines(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Checks that all parameters hold values the simulation can work with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_force.is_nan() || self.max_force < 0. {
+            return Err(ConfigError::Invalid(
+                "max_force must be non-negative".into(),
+            ));
+        }
+        if !self.center_pull_factor.is_finite() {
+            return Err(ConfigError::Invalid(
+                "center_pull_factor must be finite".into(),
+            ));
+        }
+        if !self.noise_factor.is_finite() || self.noise_factor < 0. {
+            return Err(ConfigError::Invalid(
+                "noise_factor must be non-negative".into(),
+            ));
+        }
+        if !(0. ..1.).contains(&self.heading_smoothing) {
+            return Err(ConfigError::Invalid(
+                "heading_smoothing must be at least 0 and below 1".into(),
+            ));
+        }
+        if self.fps_cap == Some(0) {
+            return Err(ConfigError::Invalid("fps_cap must be positive".into()));
+        }
+        if self.threads == Some(0) {
+            return Err(ConfigError::Invalid("threads must be positive".into()));
+        }
+        if self.rule_intervals.contains(&0) {
+            return Err(ConfigError::Invalid(
+                "rule_intervals must be positive".into(),
+            ));
+        }
+        if self.leader_min_group_size == 0 {
+            return Err(ConfigError::Invalid(
+                "leader_min_group_size must be positive".into(),
+            ));
+        }
+        if !self.cruise_speed.is_finite() || self.cruise_speed < 0. {
+            return Err(ConfigError::Invalid(
+                "cruise_speed must be non-negative".into(),
+            ));
+        }
+        if !(0. ..=1.).contains(&self.speed_damping) {
+            return Err(ConfigError::Invalid(
+                "speed_damping must be between 0 and 1".into(),
+            ));
+        }
+        if !self.trail_deposit.is_finite() || self.trail_deposit < 0. {
+            return Err(ConfigError::Invalid(
+                "trail_deposit must be non-negative".into(),
+            ));
+        }
+        if !(0. ..=1.).contains(&self.trail_decay) {
+            return Err(ConfigError::Invalid(
+                "trail_decay must be between 0 and 1".into(),
+            ));
+        }
+        if !self.trail_follow_factor.is_finite() {
+            return Err(ConfigError::Invalid(
+                "trail_follow_factor must be finite".into(),
+            ));
+        }
+        if !(0. ..=1.).contains(&self.drafting_allowance) {
+            return Err(ConfigError::Invalid(
+                "drafting_allowance must be between 0 and 1".into(),
+            ));
+        }
+        if self.min_distance.is_nan()
+            || self.min_distance < 0.
+            || self.min_distance > INFLUENCE_DISTANCE
+        {
+            return Err(ConfigError::Invalid(format!(
+                "min_distance must be between 0 and {INFLUENCE_DISTANCE}"
+            )));
+        }
+        if let WallEvasion::ByApproach(speed) = self.wall_evasion {
+            if !speed.is_finite() || speed <= 0. {
+                return Err(ConfigError::Invalid(
+                    "the wall evasion's speed must be positive".into(),
+                ));
+            }
+        }
+        if let SpawnHeading::Fixed(angle) = self.spawn_heading {
+            if !angle.is_finite() {
+                return Err(ConfigError::Invalid(
+                    "the spawn heading's angle must be finite".into(),
+                ));
+            }
+        }
+        if let SpacingModel::Potential { comfort, strength } = self.spacing_model {
+            if comfort.is_nan() || comfort <= 0. || comfort >= INFLUENCE_DISTANCE {
+                return Err(ConfigError::Invalid(format!(
+                    "the potential's comfort distance must be between 0 and {INFLUENCE_DISTANCE}"
+                )));
+            }
+            if !strength.is_finite() || strength < 0. {
+                return Err(ConfigError::Invalid(
+                    "the potential's strength must be non-negative".into(),
+                ));
+            }
+        }
+        if self.cohesion_lookahead.is_nan() || self.cohesion_lookahead < 0. {
+            return Err(ConfigError::Invalid(
+                "cohesion_lookahead must be non-negative".into(),
+            ));
+        }
+        if !(0. ..=1.).contains(&self.calm_factor) {
+            return Err(ConfigError::Invalid(
+                "calm_factor must be between 0 and 1".into(),
+            ));
+        }
+        if self.time_lens_radius.is_nan() || self.time_lens_radius <= 0. {
+            return Err(ConfigError::Invalid(
+                "time_lens_radius must be positive".into(),
+            ));
+        }
+        if self.time_lens_factor.is_nan()
+            || self.time_lens_factor <= 0.
+            || self.time_lens_factor > 1.
+        {
+            return Err(ConfigError::Invalid(
+                "time_lens_factor must be above 0 and at most 1".into(),
+            ));
+        }
+        if !self.reef_pull.is_finite() || self.reef_pull < 0. {
+            return Err(ConfigError::Invalid(
+                "reef_pull must be non-negative".into(),
+            ));
+        }
+        if !(0. ..=1.).contains(&self.reef_damping) {
+            return Err(ConfigError::Invalid(
+                "reef_damping must be between 0 and 1".into(),
+            ));
+        }
+        if let Some(points) = &self.boundary_polygon {
+            if points.len() < 3 {
+                return Err(ConfigError::Invalid(
+                    "boundary_polygon must have at least 3 points".into(),
+                ));
+            }
+            if points.iter().flatten().any(|coord| !coord.is_finite()) {
+                return Err(ConfigError::Invalid(
+                    "boundary_polygon's points must be finite".into(),
+                ));
+            }
+        }
+        if self.gravity.iter().any(|component| !component.is_finite()) {
+            return Err(ConfigError::Invalid("gravity must be finite".into()));
+        }
+        if self.whisker_count == 0 {
+            return Err(ConfigError::Invalid(
+                "whisker_count must be positive".into(),
+            ));
+        }
+        if self.whisker_length.is_nan() || self.whisker_length <= 0. {
+            return Err(ConfigError::Invalid(
+                "whisker_length must be positive".into(),
+            ));
+        }
+        if self.gust_radius.is_nan() || self.gust_radius < 0. {
+            return Err(ConfigError::Invalid(
+                "gust_radius must be non-negative".into(),
+            ));
+        }
+        if self.excitement_speed.is_nan() || self.excitement_speed < 0. {
+            return Err(ConfigError::Invalid(
+                "excitement_speed must be non-negative".into(),
+            ));
+        }
+        if self.excitement_separation.is_nan() || self.excitement_separation < 0. {
+            return Err(ConfigError::Invalid(
+                "excitement_separation must be non-negative".into(),
+            ));
+        }
+        if self.excitement_curve.is_nan() || self.excitement_curve <= 0. {
+            return Err(ConfigError::Invalid(
+                "excitement_curve must be positive".into(),
+            ));
+        }
+        if self.predator_fov.is_nan() || self.predator_fov < 0. || self.predator_fov > 360. {
+            return Err(ConfigError::Invalid(
+                "predator_fov must be between 0 and 360".into(),
+            ));
+        }
+        if self.predator_detection_range.is_nan() || self.predator_detection_range < 0. {
+            return Err(ConfigError::Invalid(
+                "predator_detection_range must be non-negative".into(),
+            ));
+        }
+        if !(0. ..1.).contains(&self.panic_transmission) {
+            return Err(ConfigError::Invalid(
+                "panic_transmission must be at least 0 and below 1".into(),
+            ));
+        }
+        if !(0. ..=1.).contains(&self.panic_decay) {
+            return Err(ConfigError::Invalid(
+                "panic_decay must be between 0 and 1".into(),
+            ));
+        }
+        if self.max_boids == 0 {
+            return Err(ConfigError::Invalid("max_boids must be positive".into()));
+        }
+        if let Some(inflow) = self.inflow {
+            if !inflow.rate.is_finite() || inflow.rate < 0. {
+                return Err(ConfigError::Invalid(
+                    "inflow rate must be non-negative".into(),
+                ));
+            }
+            let limits = speed_limits(1.);
+            if !limits.contains(&inflow.speed) {
+                return Err(ConfigError::Invalid(format!(
+                    "inflow speed must be between {} and {}",
+                    limits.start(),
+                    limits.end()
+                )));
+            }
+        }
+        if self.max_steps_per_frame == 0 {
+            return Err(ConfigError::Invalid(
+                "max_steps_per_frame must be positive".into(),
+            ));
+        }
+        if self.stats_interval == 0 {
+            return Err(ConfigError::Invalid(
+                "stats_interval must be positive".into(),
+            ));
+        }
+        if self.history_stride == 0 {
+            return Err(ConfigError::Invalid(
+                "history_stride must be positive".into(),
+            ));
+        }
+        if self.group_distance.is_nan()
+            || self.group_distance < 0.
+            || self.group_distance > INFLUENCE_DISTANCE
+        {
+            return Err(ConfigError::Invalid(format!(
+                "group_distance must be between 0 and {INFLUENCE_DISTANCE}"
+            )));
+        }
+        if self.day_cycle_period.is_nan() || self.day_cycle_period <= 0. {
+            return Err(ConfigError::Invalid(
+                "day_cycle_period must be positive".into(),
+            ));
+        }
+        if self.region_columns == 0 || self.region_rows == 0 {
+            return Err(ConfigError::Invalid(
+                "region_columns and region_rows must be positive".into(),
+            ));
+        }
+        if self.world_size.is_some_and(|size| {
+            size.iter()
+                .any(|&side| side.is_nan() || side <= 0. || side > MAX_WORLD_SIZE)
+        }) {
+            return Err(ConfigError::Invalid(format!(
+                "world_size must be positive and at most {MAX_WORLD_SIZE} on each side"
+            )));
+        }
+        if self.neighbor_rings == 0 {
+            return Err(ConfigError::Invalid(
+                "neighbor_rings must be positive".into(),
+            ));
+        }
+        if self.neighbor_samples == 0 {
+            return Err(ConfigError::Invalid(
+                "neighbor_samples must be positive".into(),
+            ));
+        }
+        let species = BOID_COLORS.len();
+        if self.species_relationships.len() != species
+            || self
+                .species_relationships
+                .iter()
+                .any(|row| row.len() != species)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "species_relationships must be a {species}x{species} matrix"
+            )));
+        }
+        if self.species_proportions.len() != species
+            || self
+                .species_proportions
+                .iter()
+                .any(|share| !share.is_finite() || *share < 0.)
+            || self.species_proportions.iter().sum::<f32>() <= 0.
+        {
+            return Err(ConfigError::Invalid(format!(
+                "species_proportions must hold {species} non-negative shares with a positive sum"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns how much boids of species `this` care about boids of species `other`. Species
+    /// missing from the relationship matrix are ignored. If colors don't affect flocking, every
+    /// species fully cares about every species.
+    pub fn relationship(&self, this: usize, other: usize) -> f32 {
+        if !self.color_affects_flocking {
+            return 1.;
+        }
+        self.species_relationships
+            .get(this)
+            .and_then(|row| row.get(other))
+            .copied()
+            .unwrap_or(0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Saves the given configuration with `to_toml` and loads it back with `load`.
+    fn reload(config: &SimConfig) -> Result<SimConfig, String> {
+        let text = config.to_toml().map_err(|err| err.to_string())?;
+        let path = std::env::temp_dir().join(format!("boids-config-{}.toml", std::process::id()));
+        std::fs::write(&path, text).map_err(|err| err.to_string())?;
+        let loaded = SimConfig::load(&path).map_err(|err| err.to_string());
+        std::fs::remove_file(&path).map_err(|err| err.to_string())?;
+        loaded
+    }
+
+    #[test]
+    fn saved_config_loads_back() {
+        let config = SimConfig {
+            seed: Some(i64::MAX as u64),
+            max_force: f32::INFINITY,
+            spacing_model: SpacingModel::Potential {
+                comfort: 20.,
+                strength: 0.5,
+            },
+            spawn_heading: SpawnHeading::Fixed(90.),
+            ..SimConfig::default()
+        };
+        let loaded = reload(&config).unwrap();
+
+        assert_eq!(loaded.seed, config.seed);
+        assert_eq!(loaded.max_force, f32::INFINITY);
+        assert_eq!(loaded.to_toml().unwrap(), config.to_toml().unwrap());
+    }
+
+    #[test]
+    fn seeds_beyond_toml_integers_are_not_saved() {
+        let config = SimConfig {
+            seed: Some(u64::MAX),
+            ..SimConfig::default()
+        };
+        let err = config.to_toml().unwrap_err().to_string();
+        assert!(err.starts_with("seed: "), "{err}");
+    }
+
+    #[test]
+    fn non_finite_floats_are_written_the_toml_way() {
+        let config = SimConfig {
+            center_pull_factor: f32::NAN,
+            max_force: f32::INFINITY,
+            trail_follow_factor: f32::NEG_INFINITY,
+            ..SimConfig::default()
+        };
+        let text = config.to_toml().unwrap();
+        assert!(text.contains("center_pull_factor = nan\n"));
+        assert!(text.contains("max_force = inf\n"));
+        assert!(text.contains("trail_follow_factor = -inf\n"));
+
+        // They're read back as they were (even when they aren't valid):
+        let parsed: SimConfig = toml::from_str(&text).unwrap();
+        assert!(parsed.center_pull_factor.is_nan());
+        assert_eq!(parsed.trail_follow_factor, f32::NEG_INFINITY);
+    }
+}
@@ -1,4 +1,11 @@
-use ggez::graphics::Color;
+use crate::color::Rgba;
+use crate::config::{
+    AlignmentMode, EdgeBehavior, EvictionPolicy, InflowSpec, LeaderMode, ScanMode, SeparationCurve,
+    SortMode, SpacingModel, SpawnHeading, WallEvasion, WallPerception,
+};
+use crate::stats::RULE_COUNT;
+use std::num::NonZeroU64;
+use std::time::Duration;
 
 pub const PROGRAM_NAME: &str = "Boids Sim";
 pub const AUTHOR: &str = "Yair Ziv";
@@ -7,30 +14,447 @@ pub const SCREEN_WIDTH: f32 = 1400f32;
 pub const SCREEN_HEIGHT: f32 = 1000f32;
 
 pub const FPS: u32 = 60;
+// The simulated time every step takes (in seconds), one frame's worth:
+pub const STEP_DURATION: f64 = 1. / FPS as f64;
+
+// Whether the simulation pauses while the window is out of focus (to save power), and how long each
+// frame takes while it's paused:
+pub const PAUSE_WHEN_UNFOCUSED: bool = true;
+pub const UNFOCUSED_FRAME_TIME: Duration = Duration::from_millis(100);
+
+// Whether the window waits for the display's refresh before showing a frame (which prevents
+// tearing and keeps the frame rate at the display's), and the maximum number of frames per second
+// when it doesn't. Without vsync or a cap, the window draws as many frames as it can:
+pub const VSYNC: bool = true;
+pub const FPS_CAP: Option<u32> = Some(120);
+
+// File the configuration is loaded from (and reloaded from when pressing F5):
+pub const CONFIG_PATH: &str = "config.toml";
+
+// Whether the state of the simulation is saved to the session file when the window is closed, and
+// whether the last saved session is loaded on startup:
+pub const SESSION_PATH: &str = "session.toml";
+pub const SAVE_SESSION_ON_QUIT: bool = false;
+pub const LOAD_LAST_SESSION: bool = false;
+
+// The window can be split into a grid of independent simulations (regions), each with its own
+// configuration. These are the number of columns and rows in that grid:
+pub const REGION_COLUMNS: usize = 1;
+pub const REGION_ROWS: usize = 1;
+
+// The size (width, height) of every region's world, which can be larger than the region's part of
+// the window (the camera shows the part around the flock with the flock cam, or the entire world
+// zoomed out without it). None makes the world exactly as large as the region. The boids start as
+// crowded as they'd be on the screen (up to the maximum number of boids), and worlds can be up to
+// MAX_WORLD_SIZE on each side, so the location grid stays small:
+pub const WORLD_SIZE: Option<[f32; 2]> = None;
+pub const MAX_WORLD_SIZE: f32 = 30000.;
+
+// Whether all boids are drawn in a single instanced draw call (fast), or each boid is drawn on its
+// own (slow, but works on GPUs where instancing is broken):
+pub const INSTANCED_RENDERING: bool = true;
+
+// Whether the boids are drawn in batches of a single species each, one species after the other,
+// instead of all together in no particular order:
+pub const GROUP_DRAW_BY_SPECIES: bool = false;
+
+// The order the boids are drawn in (within their batch, if grouped by species). Boids drawn later
+// are on top, so BySpeed draws faster boids on top and ByY draws lower boids on top. When grouped by
+// species, the sort only orders boids of the same species, and the species are layered by their
+// index (a higher species is on top of a lower one) whatever the sort:
+pub const SORT_MODE: SortMode = SortMode::None;
+
+// How much of its previous heading a boid keeps when drawn, from 0 (drawn facing exactly where it's
+// going) to just below 1 (turns very slowly). Smoothing keeps slow boids, whose tiny velocities
+// change direction a lot, from twitching on screen. It only changes how boids are drawn:
+pub const HEADING_SMOOTHING: f32 = 0.;
+
+// Statistics recording parameters. The statistics are recorded once every interval (in steps), and
+// boids are considered to be in the same group if they're connected by a chain of boids up to the
+// group distance away from each other (can't be larger than the influence distance):
+pub const STATS_INTERVAL: u64 = 30;
+pub const GROUP_DISTANCE: f32 = INFLUENCE_DISTANCE;
+
+// Timeline parameters. While running, a snapshot of the simulation is saved once every stride (in
+// steps), and only the given number of the newest snapshots are kept (each holds every boid, so
+// keeping many of them takes a lot of memory):
+pub const HISTORY_LENGTH: usize = 200;
+pub const HISTORY_STRIDE: u64 = 5;
+
+// Size of the text shown on top of the simulation:
+pub const HUD_TEXT_SIZE: f32 = 20.;
+
+// Frame time graph parameters. The graph shows the durations of the given number of the latest
+// frames, and is drawn in the bottom left corner in the given size (in pixels). The time budget of
+// a frame (1 / FPS) is drawn halfway up the graph:
+pub const FRAME_GRAPH_LENGTH: usize = 120;
+pub const FRAME_GRAPH_SIZE: [f32; 2] = [240., 80.];
+
+// Distance the target moves when nudged with the arrow keys:
+pub const TARGET_NUDGE: f32 = 3.;
+
+// Length of the velocity lines (when shown) relative to the boids' speed:
+pub const VELOCITY_LINE_SCALE: f32 = 5.;
+
+// When boids are drawn by how many close boids they have, boids with at least the given number of
+// neighbors are drawn as usual, while lone boids are drawn with the given opacity and size (relative
+// to the usual ones), and the rest in between:
+pub const MEMBERSHIP_FULL_NEIGHBORS: usize = 15;
+pub const MEMBERSHIP_MIN_ALPHA: f32 = 0.15;
+pub const MEMBERSHIP_MIN_SCALE: f32 = 0.6;
+
+// When influence is shown, every boid glows with a soft halo of the given radius, whose opacity
+// (up to the given one) grows with how much the boid influences its neighbors relative to the most
+// influential boid:
+pub const GLOW_RADIUS: f32 = 15.;
+pub const GLOW_ALPHA: f32 = 0.6;
+
+// Colors of the arrows drawn from a traced boid, showing the direction each rule gave it (in the
+// order: separation, alignment, cohesion, evasion, target, leader, center, flee, trail,
+// influencers, reefs and noise), and the length of the arrows relative to the directions:
+pub const RULE_COLORS: [Rgba; RULE_COUNT] = [
+    Rgba::RED,
+    Rgba::BLUE,
+    Rgba::GREEN,
+    Rgba::MAGENTA,
+    Rgba::YELLOW,
+    Rgba::CYAN,
+    Rgba::new(1., 0.5, 0., 1.),
+    Rgba::new(0.5, 0., 1., 1.),
+    Rgba::new(0.5, 0.3, 0.1, 1.),
+    Rgba::new(0., 0.5, 0.5, 1.),
+    Rgba::new(0., 0.5, 0., 1.),
+    Rgba::new(0.5, 0.5, 0.5, 1.),
+];
+pub const RULE_ARROW_SCALE: f32 = 50.;
+
+// Convex hulls (when shown) are drawn around groups of at least this many boids, and are this
+// opaque:
+pub const HULL_MIN_GROUP_SIZE: usize = 5;
+pub const HULL_ALPHA: f32 = 0.15;
+
+// Maximum number of flow lines (when shown) drawn from every boid to the boids influencing it:
+pub const FLOW_LINE_LINKS: usize = 4;
+
+// Day cycle parameters. When the cycle is on, the background slowly goes from day to night and
+// back over the period (in seconds), and boids are lightened at night so they stay visible:
+pub const DAY_CYCLE: bool = false;
+pub const DAY_CYCLE_PERIOD: f32 = 120.;
+pub const DAY_BACKGROUND: Rgba = Rgba::WHITE;
+pub const NIGHT_BACKGROUND: Rgba = Rgba::new(0.05, 0.05, 0.15, 1.);
+pub const NIGHT_BOID_LIGHTENING: f32 = 0.5;
+
+// Flock cam parameters. The stiffness controls how fast the camera follows the flock, and the
+// padding is the space left around the flock when framing it:
+pub const CAMERA_STIFFNESS: f32 = 2.;
+pub const CAMERA_PADDING: f32 = 150.;
+pub const MAX_CAMERA_ZOOM: f32 = 3.;
 
 // Boids parameters:
 pub const BOIDS_NUM: usize = 800;
 pub const MAX_BOID_VELOCITY: f32 = 6.;
 pub const MIN_BOID_VELOCITY: f32 = 5.;
-pub const BOID_COLORS: [Color; 7] = [
-    Color::BLACK, Color::YELLOW, Color::BLUE, Color::MAGENTA, Color::GREEN, Color::RED, Color::CYAN
+// Maximum magnitude of the combined steering of all rules in a single step:
+pub const MAX_BOID_FORCE: f32 = 2.;
+pub const BOID_COLORS: [Rgba; 7] = [
+    Rgba::BLACK, Rgba::YELLOW, Rgba::BLUE, Rgba::MAGENTA, Rgba::GREEN, Rgba::RED, Rgba::CYAN
+];
+
+// The share of the initial boids each species gets (the color at index i in BOID_COLORS gets the
+// share at index i). Shares are relative to each other, so they don't have to sum to anything in
+// particular. The default splits the boids evenly:
+pub const SPECIES_PROPORTIONS: [f32; 7] = [1.; 7];
+
+// Which way boids head when they're spawned (the initial boids, and boids spawned by right clicking
+// or by a scenario), always at a random speed between MIN_BOID_VELOCITY and MAX_BOID_VELOCITY:
+pub const SPAWN_HEADING: SpawnHeading = SpawnHeading::Random;
+
+// Whether colors matter in the alignment and cohesion rules at all. When off, every boid flocks with
+// all of its neighbors like classic boids (ignoring the relationships below), and colors are only
+// decorative:
+pub const COLOR_AFFECTS_FLOCKING: bool = true;
+
+// How much each species cares about every other species in the alignment and cohesion rules. Row i
+// holds the relationships of species i (the color at index i in BOID_COLORS) towards all species.
+// A positive value attracts, negative repels and zero ignores. Separation deliberately ignores the
+// relationships, so boids avoid bumping into boids of every species. The default only cares about
+// boids of the same color:
+pub const SPECIES_RELATIONSHIPS: [[f32; 7]; 7] = [
+    [1., 0., 0., 0., 0., 0., 0.],
+    [0., 1., 0., 0., 0., 0., 0.],
+    [0., 0., 1., 0., 0., 0., 0.],
+    [0., 0., 0., 1., 0., 0., 0.],
+    [0., 0., 0., 0., 1., 0., 0.],
+    [0., 0., 0., 0., 0., 1., 0.],
+    [0., 0., 0., 0., 0., 0., 1.],
 ];
 
+// Whether differently colored flocks that keep touching each other should merge into one flock (the
+// smaller flock takes the color of the larger one), and how many steps they need to touch for:
+pub const MERGE_ON_CONTACT: bool = false;
+pub const MERGE_CONTACT_STEPS: u32 = 90;
+
 // Parameters for boid rules:
 pub const SEPARATION_FACTOR: f32 = 0.1;
+// How the push of the separation rule depends on the distance between two boids. Linear is the
+// classic rule, where boids further away push more. Inverse pushes the same at any distance and
+// InverseSquare pushes more the closer the boids are (which reduces overlapping in dense flocks):
+pub const SEPARATION_CURVE: SeparationCurve = SeparationCurve::Linear;
+// Which grid cells the separation rule looks for close boids in. SameCellOnly only looks in each
+// boid's own cell, which is much faster for huge flocks but lets boids on opposite sides of a
+// cell's border get closer than they should:
+pub const SEPARATION_SCAN: ScanMode = ScanMode::Full;
+// Maximum push a boid can get from a single close boid in the separation rule (before the factor),
+// so curves that push more the closer the boids are can't explode when boids are very close:
+pub const MAX_SEPARATION_PUSH: f32 = 4. * STEERING_DISTANCE;
+// How boids keep their distance from each other. SeparateCohere uses the separation and cohesion
+// rules with their own factors. Potential replaces both with a single force between every two
+// close boids, pushing them apart below the comfort distance and pulling them together above it
+// (up to INFLUENCE_DISTANCE), so flocks settle evenly spaced at the comfort distance:
+pub const SPACING_MODEL: SpacingModel = SpacingModel::SeparateCohere;
+// Whether separation pushes less from close boids that are ahead and going the same way, so boids
+// can follow each other in single file (e.g: through a gap). The allowance is how much of the push
+// is removed for a boid straight ahead going exactly the same way:
+pub const DRAFTING: bool = false;
+pub const DRAFTING_ALLOWANCE: f32 = 0.8;
 pub const ALIGNMENT_FACTOR: f32 = 0.05;
+// What the alignment rule averages, the boids' velocities or only their headings:
+pub const ALIGNMENT_MODE: AlignmentMode = AlignmentMode::VelocityAverage;
+// Whether faster boids have more influence on their neighbors' alignment and cohesion, as if they're
+// more confident about where they're going. Every close boid is weighted by its speed relative to
+// MAX_BOID_VELOCITY:
+pub const SPEED_WEIGHTED_INFLUENCE: bool = false;
 pub const COHESION_FACTOR: f32 = 0.005;
+// Whether the cohesion rule steers towards where the close boids will be in the given number of
+// steps (if they keep their velocities) instead of where they are now, which tightens the flock:
+pub const PREDICTIVE_COHESION: bool = false;
+pub const COHESION_LOOKAHEAD: f32 = 10.;
+// Number of steps a boid remembers a neighbor of its own species after it goes out of range, still
+// aligning and cohering with it (less and less as the memory fades). This keeps flocks from
+// flickering apart when neighbors briefly drift out of range. Zero disables the memory:
+pub const NEIGHBOR_MEMORY: u32 = 0;
 pub const EVASION_FACTOR: f32 = 1.3;
 pub const TARGET_FACTOR: f32 = 0.0005;
+// Whether the target only pulls the front of every flock, so the front leads and cohesion brings the
+// rest along instead of the whole flock smearing towards the target. Each boid is pulled by
+// TARGET_FACTOR times the share of its close boids that are farther from the target than it:
+pub const FRONT_LED_TARGET: bool = false;
 pub const LEADER_FACTOR: f32 = 0.0005;
+// When the leader changes, the point boids follow moves from the old leader to the new one over this
+// number of steps instead of jumping (zero jumps):
+pub const LEADER_HANDOFF_STEPS: u32 = 60;
+// Who the boids follow while leaders are on. With a leader per group, every group (see
+// GROUP_DISTANCE) of at least the minimum size follows its own leader, its frontmost boid, and
+// smaller groups follow no one:
+pub const LEADER_MODE: LeaderMode = LeaderMode::Single;
+pub const LEADER_MIN_GROUP_SIZE: usize = 5;
+
+// Pulls all boids towards the center of the screen, zero disables it. Unlike the target, this is
+// meant to be very weak (e.g: 0.0001) so the flock stays loosely around the center:
+pub const CENTER_PULL_FACTOR: f32 = 0.;
+
+// Magnitude of the random steering added to every boid in each step, zero disables it. Small values
+// (e.g: 0.05) prevent the flock from freezing into a lattice without disrupting it:
+pub const NOISE_FACTOR: f32 = 0.;
+
+// Keeps the flock from staying agitated: whenever the boids' average speed is above the cruise
+// speed, every boid loses the damping's share of its velocity (within the boids' velocity limits)
+// until the flock settles. A zero cruise speed disables it. Speeds are between MIN_BOID_VELOCITY
+// and MAX_BOID_VELOCITY on each axis, so e.g: 5.5 calms a flock that has sped up:
+pub const CRUISE_SPEED: f32 = 0.;
+pub const SPEED_DAMPING: f32 = 0.02;
+
+// Trails left by the boids, like ants' pheromones. Every step, each boid deposits the deposit into
+// its cell of the grid, and every cell loses the decay's share of its trail. Boids steer towards
+// cells with stronger trails by the follow factor times the difference between the neighboring
+// cells. A zero deposit disables trails, since none are left:
+pub const TRAIL_DEPOSIT: f32 = 0.;
+pub const TRAIL_DECAY: f32 = 0.01;
+pub const TRAIL_FOLLOW_FACTOR: f32 = 0.001;
+
+// Seed of the simulation's randomness. With a seed, every run starts with the same flock:
+pub const SEED: Option<u64> = None;
+// Seed of the self test (see --selftest) when its parameters don't set one:
+pub const SELF_TEST_SEED: u64 = 0;
+// Number of steps between the reports the self test prints while it runs:
+pub const SELF_TEST_REPORT_INTERVAL: NonZeroU64 = NonZeroU64::new(100).unwrap();
+
+// Right clicking spawns a number of boids (at random positions up to the radius from the cursor).
+// Spawning more boids than the maximum evicts boids according to the eviction policy:
+pub const SPAWN_COUNT: usize = 10;
+pub const SPAWN_RADIUS: f32 = 20.;
+pub const MAX_BOIDS: usize = 2000;
+pub const EVICTION_POLICY: EvictionPolicy = EvictionPolicy::Oldest;
+
+// A picture given using --image replaces the initial boids with at most this many boids drawing it,
+// scaled to fill this share of the area. Pixels whose color is this close to the background's (in
+// RGB, where black and white are about 1.7 apart) are background:
+pub const PICTURE_BOIDS: usize = 1500;
+pub const PICTURE_FILL: f32 = 0.8;
+pub const PICTURE_COLOR_TOLERANCE: f32 = 0.15;
+
+// A stream of boids flowing into the simulation through one of the edges (none by default). Inflow
+// stops while the simulation is at its maximum number of boids, instead of evicting boids:
+pub const INFLOW: Option<InflowSpec> = None;
+
+// Swiping the mouse while holding g blows a gust that pushes boids up to the radius away from the
+// swipe along it. The push is the strength times the swipe's length (in pixels), and gets weaker
+// with the distance from the swipe:
+pub const GUST_STRENGTH: f32 = 0.05;
+pub const GUST_RADIUS: f32 = 60.;
+
+// An external excitement level (from 0 to 1, e.g: the loudness of music fed through
+// --excitement-stdin) makes the flock dance: the maximum velocity and the separation are scaled by
+// 1 + gain * level ^ curve, so at full excitement boids fly up to 1 + EXCITEMENT_SPEED times faster
+// and keep 1 + EXCITEMENT_SEPARATION times as hard apart. A curve above 1 lets quiet sounds barely
+// move the flock while loud ones move it a lot:
+pub const EXCITEMENT_SPEED: f32 = 0.5;
+pub const EXCITEMENT_SEPARATION: f32 = 1.;
+pub const EXCITEMENT_CURVE: f32 = 2.;
+
+// Acceleration applied to every boid in every step (x, y), which the flocking rules fight against.
+// When walls are restricted, a wall gravity pulls boids into holds them up like a damped spring (the
+// damping slows boids down relative to their speed), so the flock settles on it:
+pub const GRAVITY: [f32; 2] = [0., 0.];
+pub const WALL_DAMPING: f32 = 0.5;
+
+// What each edge of the screen does to boids reaching it, in the order top, right, bottom, left.
+// Evaded edges are avoided while walls are restricted and wrapped around otherwise:
+pub const EDGES: [EdgeBehavior; 4] = [EdgeBehavior::Evade; 4];
+
+// The corners of a polygon (in order, in simulation coordinates) the boids are confined to while
+// walls are restricted, evading its edges within the margin. The polygon may be concave but its
+// edges mustn't cross. Without a polygon, the boids are only confined by the edges:
+pub const BOUNDARY_POLYGON: Option<&[[f32; 2]]> = None;
 
 // Margin from window walls until evasion comes into play:
 pub const MARGIN: f32 = SCREEN_WIDTH / 10.;
 
+// How boids notice walls. With whiskers, every boid casts a number of rays (spread evenly over the
+// given angle in degrees around its heading) up to the given length, and steers away from walls the
+// rays hit, harder the closer the hit is:
+pub const WALL_PERCEPTION: WallPerception = WallPerception::Margin;
+// How hard boids evade walls when they're inside the margin. By approach, the evasion factor is
+// scaled by the boid's speed into the wall relative to the given speed, so fast boids don't
+// overshoot the margin and boids gliding along a wall aren't pushed off it:
+pub const WALL_EVASION: WallEvasion = WallEvasion::Constant;
+pub const WHISKER_COUNT: usize = 3;
+pub const WHISKER_LENGTH: f32 = 300.;
+pub const WHISKER_SPREAD: f32 = 60.;
+
+// Obstacles placed by the user. Boids start evading an obstacle when it's closer than the margin, and
+// when pushed into obstacles they're pushed back out (repeated up to a number of iterations, since
+// pushing a boid out of one obstacle may push it into another):
+pub const OBSTACLE_RADIUS: f32 = 40.;
+pub const OBSTACLE_MARGIN: f32 = 30.;
+pub const COLLISION_ITERATIONS: usize = 3;
+
+// Calm zones placed by the user. The steering of boids inside a calm zone (from all rules, but not
+// gravity) is multiplied by the calm factor, so they coast through it:
+pub const CALM_ZONE_RADIUS: f32 = 80.;
+pub const CALM_FACTOR: f32 = 0.1;
+
+// While tab is held, boids up to the radius away from the cursor are slowed down by the factor: they
+// move and steer that fraction of their usual amount every step, as if time passed slower for them:
+pub const TIME_LENS_RADIUS: f32 = 120.;
+pub const TIME_LENS_FACTOR: f32 = 0.2;
+
+// Reefs placed by the user. Boids inside a reef are pulled towards its center by the reef pull, and
+// lose up to the reef damping's share of their velocity every step (the full share at the center,
+// none at the edge), though never below the minimal velocity:
+pub const REEF_RADIUS: f32 = 150.;
+pub const REEF_PULL: f32 = 1.;
+pub const REEF_DAMPING: f32 = 0.2;
+
+// Influencers placed by the user pull every boid towards them (attractors) or push it away
+// (repellers) with their strength, which falls off with the distance: at the falloff distance it's
+// halved, at twice the distance it's a fifth and so on. The number of influencers is capped so they
+// can't slow the simulation down much. Clicking (while holding a or q) this close to an influencer
+// removes it:
+pub const INFLUENCER_STRENGTH: f32 = 0.3;
+pub const INFLUENCER_FALLOFF: f32 = 150.;
+pub const MAX_INFLUENCERS: usize = 16;
+pub const INFLUENCER_SELECT_DISTANCE: f32 = 15.;
+
+// Predators chase the closest boid they can see, which is a boid in their field of view (in
+// degrees) and up to the detection range. Without visible boids they wander around, randomly
+// turning by up to the wander angle (in degrees) every step. Boids flee from predators closer than
+// the flee distance. A predator that gets within the catch distance of a boid eats it, and is then
+// sated for a while (see PREDATOR_SATIATION), wandering around at the sated speed without hunting:
+pub const PREDATOR_FOV: f32 = 120.;
+pub const PREDATOR_DETECTION_RANGE: f32 = 250.;
+pub const PREDATOR_SPEED: f32 = 6.5;
+pub const PREDATOR_FORCE: f32 = 0.2;
+pub const PREDATOR_WANDER_ANGLE: f32 = 10.;
+pub const PREDATOR_SCALE: f32 = 2.;
+pub const PREDATOR_CATCH_DISTANCE: f32 = 8.;
+pub const PREDATOR_SATED_SPEED: f32 = 3.;
+// Number of steps a predator is sated for after catching a boid:
+pub const PREDATOR_SATIATION: u32 = 300;
+// Clicking (while holding p) this close to a predator removes it:
+pub const PREDATOR_SELECT_DISTANCE: f32 = 20.;
+pub const FLEE_DISTANCE: f32 = 100.;
+pub const FLEE_FACTOR: f32 = 1.;
+// Panic spreads through the flock from boids fleeing predators (or hit by gusts), which panic
+// completely. Every step, each boid catches the transmission's share of the panic of its most
+// panicked neighbor (fleeing the same way), and loses the decay's share of its own panic. Panicked
+// boids that don't sense a predator themselves flee in proportion to their panic. A transmission of
+// zero disables panic:
+pub const PANIC_TRANSMISSION: f32 = 0.;
+pub const PANIC_DECAY: f32 = 0.1;
+
+// Shift clicking grabs the boids up to the grab radius from the cursor, tethering them to it with a
+// spring (pulling them towards the cursor by the stiffness times the distance to it, and slowing
+// them down by the damping times their speed) until the button is released:
+pub const TETHER_GRAB_RADIUS: f32 = 20.;
+pub const TETHER_STIFFNESS: f32 = 0.01;
+pub const TETHER_DAMPING: f32 = 0.05;
+
+// Holding h sprints the boid closest to the cursor (up to the hover radius from it): its maximum
+// velocity is multiplied by the sprint multiplier and it's pushed forward by the thrust, which is
+// added to its usual steering (so it still avoids the boids around it):
+pub const SPRINT_HOVER_RADIUS: f32 = 20.;
+pub const SPRINT_MULTIPLIER: f32 = 2.;
+pub const SPRINT_THRUST: f32 = 0.5;
+
+// Splitting the flock (the S key) pushes the boids on each side of a line through the flock's
+// centroid away from the other side with this force, for this number of steps:
+pub const SPLIT_FORCE: f32 = 0.2;
+pub const SPLIT_DURATION: u32 = 180;
+
 // Boids close to others will influence their direction. This is the maximum influence distance:
 pub const STEERING_DISTANCE: f32 = 25.;
 pub const STEERING_DISTANCE_SQUARED: f32 = STEERING_DISTANCE * STEERING_DISTANCE;
 pub const INFLUENCE_DISTANCE: f32 = 75.;
 pub const INFLUENCE_DISTANCE_SQUARED: f32 = INFLUENCE_DISTANCE * INFLUENCE_DISTANCE;
-pub const LOCATION_GRID_HEIGHT: usize = (SCREEN_HEIGHT / INFLUENCE_DISTANCE) as usize + 1;
-pub const LOCATION_GRID_WIDTH: usize = (SCREEN_WIDTH / INFLUENCE_DISTANCE) as usize + 1;
\ No newline at end of file
+// Boids closer than this distance after moving are pushed apart until they're exactly this far from
+// each other, zero disables it. Can't be larger than the influence distance:
+pub const MIN_BOID_DISTANCE: f32 = 3.;
+// Number of rings of grid cells around a boid's cell that are searched for close boids. Since cells
+// are as wide as the influence distance, one ring is enough:
+pub const NEIGHBOR_RINGS: usize = 1;
+// Whether the neighbors found using the grid are compared to a (slow) check of every pair of boids
+// in each step, printing any boids the grid missed:
+pub const VERIFY_NEIGHBORS: bool = false;
+// Whether the boids' rules are calculated in parallel threads. Turning it off calculates them one
+// after the other in the same thread (whatever THREADS is), with exactly the same results, which
+// is easier to debug and profile:
+pub const PARALLEL: bool = true;
+// Number of threads the rules are split between when they're calculated in parallel. Without a
+// number, the machine's available parallelism is used. There's never more threads than rules:
+pub const THREADS: Option<usize> = None;
+// Every how many steps each rule is recalculated (in the order: separation, alignment, cohesion,
+// evasion, target, leader, center, flee, trail, influencers and reefs), reusing the directions it
+// last gave in between. Slowly changing rules (e.g: cohesion) can be recalculated less often to
+// save time in large flocks. Rules are recalculated on different steps so the slow steps don't
+// pile up. The noise is always recalculated:
+pub const RULE_INTERVALS: [u64; RULE_COUNT - 1] = [1; RULE_COUNT - 1];
+
+// Tick budget parameters. When a frame takes too long, the simulation drops the steps it couldn't
+// fit rather than trying to catch up (which only makes the next frame slower):
+pub const MAX_STEPS_PER_FRAME: u32 = 4;
+// Above this number of boids in a single grid cell, reduced accuracy mode only looks at a sample of
+// the cell's boids instead of all of them:
+pub const REDUCED_ACCURACY: bool = false;
+pub const DENSITY_THRESHOLD: usize = 40;
+pub const NEIGHBOR_SAMPLES: usize = 20;
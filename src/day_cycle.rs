@@ -0,0 +1,15 @@
+use crate::color::Rgba;
+use crate::constants::{DAY_BACKGROUND, NIGHT_BACKGROUND, NIGHT_BOID_LIGHTENING};
+
+/// Returns the background color at the given time of day, and how much the boids should be
+/// lightened (between 0 and 1) to stay visible on it. The time of day goes from 0 to 1, where 0 is
+/// midnight and 0.5 is noon.
+pub fn day_cycle_colors(time_of_day: f32) -> (Rgba, f32) {
+    // How bright it is, from 0 at midnight to 1 at noon:
+    let daylight = (1. - (std::f32::consts::TAU * time_of_day).cos()) / 2.;
+
+    (
+        NIGHT_BACKGROUND.lerp(DAY_BACKGROUND, daylight),
+        NIGHT_BOID_LIGHTENING * (1. - daylight),
+    )
+}
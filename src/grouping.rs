@@ -0,0 +1,39 @@
+/// A disjoint-set (union-find) structure over the indices `0..len`, used for splitting boids into
+/// connected groups. The representative of every set is always its smallest index, which keeps a
+/// group's representative the same across steps as long as its smallest member stays in it.
+pub struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    pub fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+        }
+    }
+
+    /// Returns the representative of the set containing `idx`.
+    pub fn find(&mut self, mut idx: usize) -> usize {
+        while self.parent[idx] != idx {
+            // Path halving, skip every other node on the way up:
+            self.parent[idx] = self.parent[self.parent[idx]];
+            idx = self.parent[idx];
+        }
+        idx
+    }
+
+    /// Merges the sets containing `a` and `b`.
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a < b {
+            self.parent[b] = a;
+        } else {
+            self.parent[a] = b;
+        }
+    }
+
+    /// Returns the representative of each index's set.
+    pub fn into_representatives(mut self) -> Vec<usize> {
+        (0..self.parent.len()).map(|idx| self.find(idx)).collect()
+    }
+}
@@ -0,0 +1,41 @@
+use ggez::glam::Vec2;
+
+/// Returns the convex hull of the given points (using Andrew's monotone chain), as its corners in
+/// counter-clockwise order (clockwise on screen, where y grows downwards). Points on the hull's
+/// edges aren't corners. With less than three distinct points there's no hull to speak of, so the
+/// distinct points are returned as they are.
+pub fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    // Whether going from a to b to c turns clockwise (or doesn't turn at all):
+    let turns_clockwise = |a: Vec2, b: Vec2, c: Vec2| (b - a).perp_dot(c - a) <= 0.;
+
+    // Build the lower half from left to right, and then the upper half back from right to left
+    // (starting at the lower half's last corner), dropping the last corner whenever the hull would
+    // turn the wrong way:
+    let mut hull: Vec<Vec2> = Vec::with_capacity(points.len() + 1);
+    for &point in points.iter() {
+        while hull.len() >= 2 && turns_clockwise(hull[hull.len() - 2], hull[hull.len() - 1], point)
+        {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+    let lower_len = hull.len() + 1;
+    for &point in points.iter().rev().skip(1) {
+        while hull.len() >= lower_len
+            && turns_clockwise(hull[hull.len() - 2], hull[hull.len() - 1], point)
+        {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+
+    // The last corner is the first one again:
+    hull.pop();
+    hull
+}
@@ -1,19 +1,117 @@
-use crate::boid::{Boid, GridBoid};
+use crate::boid::Boid;
+use crate::color::Rgba;
+use crate::config::{ConfigError, LeaderMode, SimConfig};
 use crate::constants::*;
+use crate::day_cycle::day_cycle_colors;
+use crate::obstacle::{CalmZone, Influencer, Obstacle, Reef};
+use crate::picture::place_picture;
+use crate::region::Region;
+use crate::scenario::Scenario;
+use crate::session::Session;
+use crate::simulation::Simulation;
+use crate::stats::{RuleTrace, StatsRecorder, TraceRecorder};
 use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event::{EventHandler, MouseButton};
 use ggez::glam::Vec2;
-use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, InstanceArray, Mesh};
-use ggez::input::keyboard::KeyInput;
+use ggez::graphics::{
+    Canvas, Color, DrawMode, DrawParam, InstanceArray, Mesh, MeshBuilder, Rect, Text,
+};
+use ggez::input::keyboard::{KeyInput, KeyMods};
 use ggez::winit::event::VirtualKeyCode;
-use ggez::{Context, ContextBuilder, GameError};
+use ggez::{Context, ContextBuilder, GameError, GameResult};
+use image::RgbaImage;
 use rand::Rng;
-use std::collections::{HashMap, HashSet};
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 mod boid;
+mod camera;
+mod color;
+mod config;
 mod constants;
+mod day_cycle;
+mod grouping;
+mod hull;
+mod obstacle;
+mod picture;
+mod predator;
+mod region;
+mod scenario;
+mod session;
+mod simulation;
+mod stats;
+mod svg;
+mod toml_text;
 
 fn main() {
+    // Load the scenario given using --scenario and the picture given using --image, refusing to
+    // start if either is broken, read the number of steps given using --selftest, and check
+    // whether the excitement level should be read using --excitement-stdin:
+    let mut args = std::env::args().skip(1);
+    let mut scenario = None;
+    let mut picture = None;
+    let mut self_test_steps = None;
+    let mut excitement_stdin = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scenario" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--scenario needs the path of a scenario file");
+                    std::process::exit(1);
+                };
+                match Scenario::load(&path) {
+                    Ok(loaded) => scenario = Some((path, loaded)),
+                    Err(err) => {
+                        eprintln!("{path}: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--image" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--image needs the path of a picture");
+                    std::process::exit(1);
+                };
+                match image::open(&path) {
+                    Ok(loaded) => picture = Some(loaded.into_rgba8()),
+                    Err(err) => {
+                        eprintln!("{path}: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--selftest" => match args.next().map(|steps| steps.parse::<u64>()) {
+                Some(Ok(steps)) => self_test_steps = Some(steps),
+                _ => {
+                    eprintln!("--selftest needs the number of steps to run");
+                    std::process::exit(1);
+                }
+            },
+            "--excitement-stdin" => excitement_stdin = true,
+            _ => {}
+        }
+    }
+
+    // A self test runs without a window:
+    if let Some(steps) = self_test_steps {
+        self_test(
+            steps,
+            scenario.as_ref().map(|(_, scenario)| scenario),
+            picture.as_ref(),
+        );
+        return;
+    }
+
+    // The scenario's parameters replace the main configuration file (reading it before the window
+    // opens, since the window is set up by it):
+    let config = match &scenario {
+        Some((_, scenario)) => scenario.config.clone(),
+        None => BoidsSim::load_config(CONFIG_PATH).unwrap_or_default(),
+    };
+
     // Initialize window:
     let (context, event_loop) = ContextBuilder::new(PROGRAM_NAME, AUTHOR)
         .window_mode(
@@ -23,503 +121,1624 @@ fn main() {
                 .min_dimensions(SCREEN_WIDTH, SCREEN_HEIGHT)
                 .resizable(false),
         )
-        .window_setup(WindowSetup::default().title(PROGRAM_NAME))
+        .window_setup(
+            WindowSetup::default()
+                .title(PROGRAM_NAME)
+                .vsync(config.vsync),
+        )
         .build()
         .expect("Couldn't initialize window");
 
     // Initialize simulation:
-    let sim = BoidsSim::new();
+    let mut sim = BoidsSim::new(config, scenario, picture.as_ref());
+    if excitement_stdin {
+        sim.excitement = Some(read_excitement());
+    }
 
     // Run simulation:
     ggez::event::run(context, event_loop, sim);
 }
 
-fn randf(a: f32, b: f32) -> f32 {
-    let mut rand = rand::thread_rng();
-    rand.gen_range(a..b)
-}
-
-/// Runs the given function for all adjacent cells in the grid AND THE CURRENT CELL.
-fn run_for_neighbor_cells<F>(row: usize, col: usize, width: usize, height: usize, mut f: F)
-where
-    F: FnMut(usize, usize),
-{
-    for row_shift in -1isize..=1isize {
-        // Validate row:
-        let current_row = row_shift + row as isize;
-        if current_row >= 0 && current_row < height as isize {
-            for col_shift in -1isize..=1isize {
-                // Validate column:
-                let current_col = col_shift + col as isize;
-                if current_col >= 0 && current_col < width as isize {
-                    // Fun method:
-                    f(current_row as usize, current_col as usize);
-                }
+/// Starts reading excitement levels from the standard input (one number from 0 to 1 per line, e.g:
+/// the loudness of music analyzed by another program), and returns the latest level read (as the
+/// bits of an `f32`, starting at 0). Lines that aren't numbers are ignored.
+fn read_excitement() -> Arc<AtomicU32> {
+    let level = Arc::new(AtomicU32::new(0f32.to_bits()));
+    let latest = Arc::clone(&level);
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if let Ok(read) = line.trim().parse::<f32>() {
+                latest.store(read.to_bits(), Ordering::Relaxed);
             }
         }
+    });
+    level
+}
+
+/// Runs a simulation without a window for the given number of steps, and prints its final state, so
+/// the simulation can be checked where there's no GPU. The simulation uses the scenario if one is
+/// given, or the default parameters otherwise (not the configuration file, so the results only change
+/// when the code does), and the seed is SELF_TEST_SEED unless the parameters set one. The boids draw
+/// the picture if one is given. The checksum of the boids' positions changes whenever the flocking
+/// changes.
+fn self_test(steps: u64, scenario: Option<&Scenario>, picture: Option<&RgbaImage>) {
+    let mut config = scenario.map_or_else(SimConfig::default, |scenario| scenario.config.clone());
+    config.seed = config.seed.or(Some(SELF_TEST_SEED));
+    let size = config
+        .world_size
+        .map_or(Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT), Vec2::from);
+    let mut sim = Simulation::new(config, size);
+    if let Some(scenario) = scenario {
+        scenario.apply(&mut sim);
     }
+    if let Some(picture) = picture {
+        place_picture(&mut sim, picture);
+    }
+    // Report along the way, so a changed checksum can be traced back to when the runs diverged:
+    sim.set_tick_hook(SELF_TEST_REPORT_INTERVAL, |sim| {
+        println!(
+            "step {}: polarization {:.6}",
+            sim.step_count(),
+            sim.polarization()
+        );
+    });
+    for _ in 0..steps {
+        sim.step();
+    }
+
+    // Hash the positions' bits with FNV-1a, which (unlike the standard library's hasher) is the same
+    // on every platform and Rust version:
+    let checksum = sim
+        .iter_boids()
+        .flat_map(|boid| boid.pos.to_array())
+        .flat_map(|coord| coord.to_bits().to_le_bytes())
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+    let centroid = sim.centroid();
+    println!("steps: {}", sim.step_count());
+    println!("boids: {}", sim.iter_boids().count());
+    println!("polarization: {:.6}", sim.polarization());
+    println!("centroid: ({:.3}, {:.3})", centroid.x, centroid.y);
+    println!("checksum: {checksum:016x}");
+}
+
+/// Returns the rectangle with the given opposite corners.
+fn rect_between(corner: Vec2, opposite: Vec2) -> Rect {
+    let min = corner.min(opposite);
+    let size = (corner - opposite).abs();
+    Rect::new(min.x, min.y, size.x, size.y)
+}
+
+/// Returns the number of seconds since the unix epoch, used for naming files.
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns the path of the configuration file of the region with the given index. Regions are
+/// numbered row by row, starting at the top left.
+fn region_config_path(index: usize) -> String {
+    format!("config_{index}.toml")
+}
+
+/// Which convex hulls are drawn around the boids.
+#[derive(Clone, Copy)]
+enum Hulls {
+    None,
+    // A single hull around the whole flock:
+    Flock,
+    // A hull around every group, in the group's color:
+    Groups,
 }
 
 struct BoidsSim {
-    // The grid divides the screen into cells, and each cell contains a list of the boids in it. The
-    // grid only saves indices to the 'boids' vector (to avoid references):
-    location_grid: Vec<Vec<HashSet<usize>>>,
+    // The configuration loaded from CONFIG_PATH (or from the scenario, if one was given), whose
+    // window-wide parameters (e.g: the number of regions) apply to all regions:
+    config: SimConfig,
+
+    // The path of the scenario the simulation started from, if one was given:
+    scenario_path: Option<String>,
+
+    // The independent simulations the window is split into:
+    regions: Vec<Region>,
+
+    // Index of the region whose target is currently being dragged by the mouse:
+    dragging_target: Option<usize>,
+
+    // Index of the region whose boids are being selected with a selection box, and the corners of
+    // the box (in the region's simulation coordinates) while the mouse drags it:
+    selecting: Option<(usize, Vec2, Vec2)>,
+
+    // Index of the region whose boid is sprinting while h is held:
+    sprinting: Option<usize>,
+
+    // Index of the region whose boids are currently grabbed by the mouse:
+    grabbing: Option<usize>,
+
+    // Whether the cameras should follow the flocks:
+    flock_cam: bool,
+
+    // Whether the window is focused:
+    focused: bool,
 
-    // All boids in the simulation and their indices in the location grid:
-    boids: Vec<GridBoid>,
+    // Whether each boid's velocity should be drawn:
+    show_velocities: bool,
 
-    // A location all boids will aim towards:
-    target: Option<Vec2>,
+    // Whether boids are drawn as lines to the boids influencing them instead of on their own:
+    show_flow_lines: bool,
 
-    // Whether boids should avoid walls or not:
-    restrict_walls: bool,
+    // Whether the number of boids in every grid cell is shown:
+    show_occupancy: bool,
 
-    // Index of the leader boid:
-    leader_idx: Option<usize>,
+    // Whether boids are drawn bolder the more close boids they have, and whether they glow the more
+    // they influence the boids close to them:
+    show_membership: bool,
+    show_influence: bool,
+
+    // Index of the region whose grid cell is inspected, and the inspected point (in the region's
+    // simulation coordinates):
+    inspected: Option<(usize, Vec2)>,
+
+    // The convex hulls drawn around the boids:
+    hulls: Hulls,
+
+    // Number of steps dropped so far because frames took too long:
+    dropped_steps: u64,
+
+    // The durations (in seconds) of the latest frames, from the oldest to the newest, and whether
+    // they should be drawn as a graph:
+    frame_times: VecDeque<f32>,
+    show_frame_graph: bool,
+
+    // Index (in BOID_COLORS) of the species of the boids spawned by right clicking:
+    spawn_color_idx: usize,
+
+    // Whether the simulation is paused, and the index of the snapshot shown from the regions'
+    // history (if scrubbing through it):
+    paused: bool,
+    timeline: Option<usize>,
+
+    // Records statistics of the regions while recording is on:
+    recorder: Option<StatsRecorder>,
+    // Records the trajectory of the traced boid while tracing is on, with its region's index:
+    tracer: Option<(usize, TraceRecorder)>,
+
+    // The current time of day (from 0 at midnight to 1 at the next midnight), and whether it
+    // advances:
+    time_of_day: f32,
+    day_cycle: bool,
+
+    // When the last frame started, for capping the frame rate:
+    last_frame: Instant,
+
+    // The latest excitement level read from the standard input, if it's read (see
+    // `read_excitement`):
+    excitement: Option<Arc<AtomicU32>>,
 }
 
 impl BoidsSim {
-    pub fn new() -> Self {
-        let (location_grid, boids) = Self::get_random_boids();
+    /// Creates the simulation with the given parameters, from the given scenario (and the path it
+    /// was loaded from) if one is given, with boids drawing the given picture if one is given.
+    pub fn new(
+        config: SimConfig,
+        scenario: Option<(String, Scenario)>,
+        picture: Option<&RgbaImage>,
+    ) -> Self {
+        // All regions start from the same seed, so they have the same initial conditions. A random
+        // seed is kept in TOML's integer range, so saved configurations (see F6) can be loaded:
+        let seed = config
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen_range(0..=i64::MAX as u64));
+
+        // Split the window to regions:
+        let (columns, rows) = (config.region_columns, config.region_rows);
+        let size = Vec2::new(SCREEN_WIDTH / columns as f32, SCREEN_HEIGHT / rows as f32);
+        let mut regions: Vec<Region> = (0..columns * rows)
+            .map(|i| {
+                // A broken region file can't keep a configuration the region doesn't have yet, so
+                // the region starts with the main configuration:
+                let mut region_config =
+                    Self::load_region_config(&config, i).unwrap_or_else(|| config.clone());
+                region_config.seed = region_config.seed.or(Some(seed));
+
+                let (row, col) = (i / columns, i % columns);
+                let bounds = Rect::new(col as f32 * size.x, row as f32 * size.y, size.x, size.y);
+                Region::new(region_config, bounds)
+            })
+            .collect();
+
+        if let Some((_, scenario)) = &scenario {
+            for region in regions.iter_mut() {
+                scenario.apply(&mut region.sim);
+            }
+        } else if config.load_last_session {
+            Self::load_session(&mut regions);
+        }
+        if let Some(picture) = picture {
+            for region in regions.iter_mut() {
+                place_picture(&mut region.sim, picture);
+            }
+        }
 
         Self {
-            location_grid,
-            boids,
-            target: None,
-            restrict_walls: true,
-            leader_idx: None,
+            scenario_path: scenario.map(|(path, _)| path),
+            regions,
+            dragging_target: None,
+            grabbing: None,
+            selecting: None,
+            sprinting: None,
+            flock_cam: false,
+            focused: true,
+            show_velocities: false,
+            show_flow_lines: false,
+            show_occupancy: false,
+            show_membership: false,
+            show_influence: false,
+            inspected: None,
+            hulls: Hulls::None,
+            dropped_steps: 0,
+            frame_times: VecDeque::with_capacity(FRAME_GRAPH_LENGTH + 1),
+            show_frame_graph: false,
+            spawn_color_idx: 0,
+            paused: false,
+            timeline: None,
+            recorder: None,
+            tracer: None,
+            time_of_day: 0.5,
+            day_cycle: config.day_cycle,
+            last_frame: Instant::now(),
+            excitement: None,
+            config,
         }
     }
 
-    fn get_random_boids() -> (Vec<Vec<HashSet<usize>>>, Vec<GridBoid>) {
-        // Create the location grid:
-        let mut location_grid =
-            vec![vec![HashSet::new(); LOCATION_GRID_WIDTH]; LOCATION_GRID_HEIGHT];
-
-        // Create boids (position them at the center of each location cell):
-        let boids: Vec<GridBoid> = (0..BOIDS_NUM)
-            .map(|i| {
-                // Create boid with no particular color:
-                let boid = Boid::new(
-                    randf(MARGIN, SCREEN_WIDTH - MARGIN),
-                    randf(MARGIN, SCREEN_HEIGHT - MARGIN),
-                    BOID_COLORS[i % BOID_COLORS.len()],
-                );
-
-                // Calculate row and column:
-                let (col, row) = (
-                    (boid.pos().x / INFLUENCE_DISTANCE) as usize,
-                    (boid.pos().y / INFLUENCE_DISTANCE) as usize,
-                );
+    /// Returns whether the simulation is paused because the window lost focus.
+    fn is_asleep(&self) -> bool {
+        !self.focused && self.config.pause_when_unfocused
+    }
 
-                // Change add index to location grid:
-                location_grid[row][col].insert(i);
+    /// Loads the configuration file in the given path. If it doesn't exist, `None` is returned
+    /// silently. If it exists but couldn't be loaded, the error is printed and `None` is returned.
+    fn load_config(path: &str) -> Option<SimConfig> {
+        match SimConfig::load(path) {
+            Ok(config) => Some(config),
+            Err(ConfigError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                eprintln!("{path}: {err}");
+                None
+            }
+        }
+    }
 
-                // Return GridBoid:
-                GridBoid { boid, row, col }
-            })
-            .collect();
+    /// Restores the session saved in SESSION_PATH into the given regions. If there's no saved
+    /// session, the regions are left alone silently.
+    fn load_session(regions: &mut [Region]) {
+        match Session::load(SESSION_PATH) {
+            Ok(session) => {
+                session.restore(regions.iter_mut().map(|region| &mut region.sim));
+                println!("Restored the session from {SESSION_PATH}");
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!("{SESSION_PATH}: {err}"),
+        }
+    }
 
-        (location_grid, boids)
+    /// Loads the configuration of the region with the given index. Regions without a configuration
+    /// file of their own use the main configuration. If the region's file exists but couldn't be
+    /// loaded, the error is printed and `None` is returned.
+    fn load_region_config(config: &SimConfig, index: usize) -> Option<SimConfig> {
+        if config.region_columns * config.region_rows == 1 {
+            return Some(config.clone());
+        }
+        let path = region_config_path(index);
+        match SimConfig::load(&path) {
+            Ok(config) => Some(config),
+            Err(ConfigError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                Some(config.clone())
+            }
+            Err(err) => {
+                eprintln!("{path}: {err}");
+                None
+            }
+        }
     }
 
-    fn update_boids(&mut self) {
-        // Recalculate indices:
-        self.recalculate_boid_indices();
+    /// Returns the index of the region containing the given point in the window.
+    fn region_at(&self, point: Vec2) -> Option<usize> {
+        self.regions
+            .iter()
+            .position(|region| region.bounds.contains(point))
+    }
 
-        // Update directions:
-        self.update_boids_directions();
+    /// Draws a soft halo around every visible boid in its color, whose opacity is proportional to
+    /// the influence the boid has on its neighbors (relative to the most influential boid). All
+    /// halos are drawn as a single mesh.
+    fn draw_glow(
+        ctx: &Context,
+        canvas: &mut Canvas,
+        sim: &Simulation,
+        visible: Rect,
+        lightening: f32,
+    ) -> GameResult {
+        let received = sim.influence_received();
+        let strongest = received.iter().copied().fold(0., f32::max);
+        if strongest <= 0. {
+            return Ok(());
+        }
 
-        // Move boids:
-        for grid_boid in self.boids.iter_mut() {
-            grid_boid.boid.go_forward();
+        // Every halo is a few stacked circles that get smaller towards the boid, so it's brightest
+        // in the middle and fades outwards:
+        let mut builder = MeshBuilder::new();
+        for (boid, influence) in sim.boids().zip(received) {
+            if influence <= 0. || !visible.contains(boid.pos()) {
+                continue;
+            }
+            let color = boid.color().lerp(Rgba::WHITE, lightening);
+            let alpha = GLOW_ALPHA * influence / strongest / 3.;
+            for ring in 1..=3 {
+                let radius = GLOW_RADIUS * ring as f32 / 3.;
+                let color = Color::from(Rgba { a: alpha, ..color });
+                builder.circle(DrawMode::fill(), boid.pos(), radius, 1., color)?;
+            }
         }
+        canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        Ok(())
     }
 
-    fn update_boids_directions(&mut self) {
-        // Calculate new directions for each boid based on these rules:
-        // 1) Don't go towards other boids (Separation).
-        // 2) Align direction with close boids' direction (Alignment).
-        // 3) Go towards the average location of close boids (Cohesion).
-        // 4) Avoid screen walls (Evasion).
-        // Calculate each rule in a different thread.
-        let directions_matrix = crossbeam::thread::scope(|s| {
-            let sep_thread = s.spawn(|_| self.calc_separation_directions());
-            let align_thread = s.spawn(|_| self.calc_alignment_directions());
-            let coh_thread = s.spawn(|_| self.calc_cohesion_directions());
-            let eva_thread = s.spawn(|_| {
-                if self.restrict_walls {
-                    self.calc_evasion_directions()
-                } else {
-                    (0..self.boids.len()).map(|_| Vec2::ZERO).collect()
-                }
-            });
-            let target_thread = s.spawn(|_| self.calc_target_directions());
-            let leader_thread = s.spawn(|_| self.calc_leader_directions());
-
-            // Join all threads and put in a vector:
-            vec![
-                sep_thread.join().expect("Error in separation thread"),
-                align_thread.join().expect("Error in separation thread"),
-                coh_thread.join().expect("Error in separation thread"),
-                eva_thread.join().expect("Error in separation thread"),
-                target_thread.join().expect("Error in target thread"),
-                leader_thread.join().expect("Error in leader thread"),
-            ]
-        })
-        .expect("Error creating threads");
-
-        // Each row in the matrix is a different rule, combine them to a vector with size boids.len:
-        let directions_vector: Vec<Vec2> = (0..self.boids.len())
-            .map(move |i| {
-                let mut sum = Vec2::ZERO;
-                for rule_idx in 0..directions_matrix.len() {
-                    sum += directions_matrix[rule_idx][i];
-                }
-                sum
-            })
-            .collect();
+    /// Draws a line from every boid in the direction it's going, with a length proportional to its
+    /// speed. All lines are drawn as a single mesh.
+    fn draw_velocities(
+        ctx: &Context,
+        canvas: &mut Canvas,
+        sim: &Simulation,
+        lightening: f32,
+    ) -> GameResult {
+        if sim.is_empty() {
+            return Ok(());
+        }
 
-        // For each boid, add directions:
-        for (i, direction) in directions_vector.into_iter().enumerate() {
-            self.boids[i].boid.add_dir(direction);
+        let mut builder = MeshBuilder::new();
+        for boid in sim.iter_boids() {
+            let color = BOID_COLORS[boid.species_index].lerp(Rgba::WHITE, lightening);
+            builder.line(
+                &[boid.pos, boid.pos + VELOCITY_LINE_SCALE * boid.velocity],
+                1.,
+                Color::from(color),
+            )?;
         }
+        canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+
+        Ok(())
     }
 
-    /// Calculates a vector of length `self.boids.len()` of directions towards the target. Each
-    /// direction corresponds to a single boid in the `self.boids` vector.
-    /// If a target is not specified, all directions are `Vec2::Zero`.
-    fn calc_target_directions(&self) -> Vec<Vec2> {
-        // If there is a target, move the boids towards it:
-        if let Some(target_pos) = self.target {
-            (0..self.boids.len())
-                .map(|i| TARGET_FACTOR * (target_pos - self.boids[i].boid.pos()))
-                .collect()
-        } else {
-            vec![Vec2::ZERO; self.boids.len()]
+    /// Draws a line from every visible boid to each of the boids influencing it (up to
+    /// `FLOW_LINE_LINKS` of them), in the boid's color.
+    fn draw_flow_lines(
+        ctx: &Context,
+        canvas: &mut Canvas,
+        sim: &Simulation,
+        visible: Rect,
+        lightening: f32,
+    ) -> GameResult {
+        let links = sim.influence_links(FLOW_LINE_LINKS);
+        let mut builder = MeshBuilder::new();
+        let mut has_lines = false;
+        for (boid, other) in links
+            .into_iter()
+            .filter(|(boid, _)| visible.contains(boid.pos()))
+        {
+            builder.line(
+                &[boid.pos(), other.pos()],
+                1.,
+                Color::from(boid.color().lerp(Rgba::WHITE, lightening)),
+            )?;
+            has_lines = true;
+        }
+        // A mesh without any lines can't be drawn:
+        if has_lines {
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
         }
+
+        Ok(())
     }
 
-    /// Calculates a vector of length `self.boids.len()` of directions towards the leader. Each
-    /// direction corresponds to a single boid in the `self.boids` vector.
-    /// If a leader is not specified, all directions are `Vec2::Zero`.
-    fn calc_leader_directions(&self) -> Vec<Vec2> {
-        // If there is a leader , move the boids towards it:
-        if let Some(idx) = self.leader_idx {
-            (0..self.boids.len())
-                .map(|i| LEADER_FACTOR * (self.boids[idx].boid.pos() - self.boids[i].boid.pos()))
-                .collect()
-        } else {
-            vec![Vec2::ZERO; self.boids.len()]
+    /// Draws an arrow from the given position for every rule's direction in the trace, in the rule's
+    /// color. Rules that didn't steer the boid at all aren't drawn.
+    fn draw_rule_arrows(
+        ctx: &Context,
+        canvas: &mut Canvas,
+        pos: Vec2,
+        trace: &RuleTrace,
+    ) -> GameResult {
+        let mut builder = MeshBuilder::new();
+        let mut has_arrows = false;
+        for (&(_, direction), color) in trace.contributions.iter().zip(RULE_COLORS) {
+            let Some(heading) = direction.try_normalize() else {
+                continue;
+            };
+            let tip = pos + RULE_ARROW_SCALE * direction;
+            let back = -5. * heading;
+            builder.line(
+                &[
+                    tip + Vec2::from_angle(0.5).rotate(back),
+                    tip,
+                    tip + Vec2::from_angle(-0.5).rotate(back),
+                ],
+                1.,
+                Color::from(color),
+            )?;
+            builder.line(&[pos, tip], 1., Color::from(color))?;
+            has_arrows = true;
+        }
+        // A mesh without any lines can't be drawn:
+        if has_arrows {
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
         }
+
+        Ok(())
     }
 
-    /// According to boids' rule of separation, returns a vector containing the directions that
-    /// point away from nearby boids.
-    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
-    /// vector.
-    fn calc_separation_directions(&self) -> Vec<Vec2> {
-        // Create a cache for storing results of vector subtraction (saves half of computations
-        // because after calculating a - b we don't need to calculate b - a):
-        let mut sub_cache: HashMap<(&GridBoid, &GridBoid), Vec2> = HashMap::new();
+    /// Draws the number of boids in every visible grid cell that has any boids over the cell, which is
+    /// colored from green (few boids) to red (the most crowded cell).
+    fn draw_occupancy(
+        ctx: &Context,
+        canvas: &mut Canvas,
+        sim: &Simulation,
+        view: Rect,
+    ) -> GameResult {
+        let most = sim
+            .cell_occupancy()
+            .map(|(_, count)| count)
+            .max()
+            .unwrap_or(0);
+        if most == 0 {
+            return Ok(());
+        }
 
-        // Create the vector:
-        self.boids
-            .iter()
-            .enumerate()
-            .map(|(i, this)| {
-                // Initial direction vector:
-                let mut dir = Vec2::ZERO;
-
-                // For each adjacent cell and the current one:
-                run_for_neighbor_cells(
-                    this.row,
-                    this.col,
-                    LOCATION_GRID_WIDTH,
-                    LOCATION_GRID_HEIGHT,
-                    |row, col| {
-                        // Loop over all boids in the cell:
-                        for &other_idx in self.location_grid[row][col].iter() {
-                            // Avoid current boid:
-                            if i == other_idx {
-                                continue;
-                            }
-                            // Check that the distance between boids is within the influence radius:
-                            let other = &self.boids[other_idx];
-                            if this.boid.pos().distance_squared(other.boid.pos())
-                                > STEERING_DISTANCE_SQUARED
-                            {
-                                continue;
-                            }
-
-                            // Check if the calculation is saved in the sub cache:
-                            if let Some(&sub) = sub_cache.get(&(other, this)) {
-                                // Remember that saved calculation is this - other and we need other - this:
-                                dir -= sub;
-                            }
-                            // If not calculate it and save in cache:
-                            else {
-                                let sub = other.boid.pos() - this.boid.pos();
-                                sub_cache.insert((this, other), sub);
-                                dir += sub;
-                            }
-                        }
-                    },
-                );
-                // Don't forget to invert and multiply by factor:
-                -SEPARATION_FACTOR * dir
-            })
-            .collect()
+        let mut builder = MeshBuilder::new();
+        let mut counts = Vec::new();
+        for (cell, count) in sim.cell_occupancy() {
+            if count == 0 || !cell.overlaps(&view) {
+                continue;
+            }
+            let color = Rgba::GREEN.lerp(Rgba::RED, count as f32 / most as f32);
+            builder.rectangle(
+                DrawMode::fill(),
+                cell,
+                Color::from(Rgba::new(color.r, color.g, color.b, 0.25)),
+            )?;
+            counts.push((cell, count, color));
+        }
+        if !counts.is_empty() {
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
+        for (cell, count, color) in counts {
+            canvas.draw(
+                Text::new(count.to_string()).set_scale(HUD_TEXT_SIZE),
+                DrawParam::default()
+                    .dest(Vec2::from(cell.point()) + Vec2::splat(5.))
+                    .color(Color::from(color)),
+            );
+        }
+
+        Ok(())
     }
 
-    /// According to boids' rule of alignment, returns a vector containing the difference between
-    /// each boid's current direction and the average direction of boids close to it who share its
-    /// color.
-    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
-    /// vector.
-    fn calc_alignment_directions(&self) -> Vec<Vec2> {
-        self.boids
+    /// Draws a single region in its part of the window. The boids are lightened by the given amount
+    /// (see `day_cycle_colors`). When scrubbing through the timeline, the region's snapshot is
+    /// drawn instead of its simulation.
+    fn draw_region(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        index: usize,
+        region: &Region,
+        lightening: f32,
+    ) -> GameResult {
+        canvas.set_screen_coordinates(region.screen_coordinates());
+        canvas.set_scissor_rect(region.bounds)?;
+        let sim = self
+            .timeline
+            .and_then(|index| region.history.get(index))
+            .unwrap_or(&region.sim);
+
+        // Only boids in the camera's view are drawn. Boids whose center is slightly outside the view
+        // can still be partly visible, so the view is enlarged by the size of a boid:
+        let boid_size = Boid::get_boid_shape()
             .iter()
-            .map(|this| {
-                // Initialize sum and counter:
-                let mut sum = Vec2::ZERO;
-                let mut count = 0usize;
-
-                // Calculate the average direction of nearby boids:
-                run_for_neighbor_cells(
-                    this.row,
-                    this.col,
-                    LOCATION_GRID_WIDTH,
-                    LOCATION_GRID_HEIGHT,
-                    |row, col| {
-                        for other_idx in &self.location_grid[row][col] {
-                            // Check that the distance between boids is within the influence radius:
-                            let other = &self.boids[*other_idx];
-                            if this.boid.pos().distance_squared(other.boid.pos())
-                                > INFLUENCE_DISTANCE_SQUARED
-                            {
-                                continue;
-                            }
-                            // Check if they have different colors:
-                            else if this.boid.color() != other.boid.color() {
-                                continue;
-                            }
-
-                            // Add current direction to average (this includes our direction):
-                            sum += other.boid.speed();
-                            count += 1;
-                        }
-                    },
+            .map(|point| Vec2::new(point.x, point.y).length())
+            .fold(0., f32::max);
+        let view = region.camera.view_rect();
+        let visible = Rect::new(
+            view.x - boid_size,
+            view.y - boid_size,
+            view.w + 2. * boid_size,
+            view.h + 2. * boid_size,
+        );
+
+        // Calculate the visible boids' drawing parameters, in a batch per species if they're grouped
+        // by species or all in one batch otherwise. Flow lines replace the boids, so there are none.
+        // When showing membership, boids with few close boids are drawn fainter and smaller:
+        let batch_count = if self.config.group_draw_by_species {
+            BOID_COLORS.len()
+        } else {
+            1
+        };
+        let mut batches: Vec<Vec<DrawParam>> = vec![Vec::new(); batch_count];
+        if !self.show_flow_lines {
+            let counts = self.show_membership.then(|| sim.neighbor_counts());
+            let mut boids: Vec<(&Boid, f32)> = sim
+                .boids()
+                .enumerate()
+                .filter(|(_, boid)| visible.contains(boid.pos()))
+                .map(|(idx, boid)| {
+                    let membership = counts.as_ref().map_or(1., |counts| {
+                        (counts[idx] as f32 / MEMBERSHIP_FULL_NEIGHBORS as f32).min(1.)
+                    });
+                    (boid, membership)
+                })
+                .collect();
+            // Instanced meshes are drawn in order, so sorting the boids layers them. Grouped by
+            // species, the sort only layers the boids within each species, and higher species are
+            // drawn on top of lower species whatever the sort:
+            let sort_mode = self.config.sort_mode;
+            boids.sort_by(|(a, _), (b, _)| sort_mode.key(a).total_cmp(&sort_mode.key(b)));
+            for (boid, membership) in boids {
+                let color = boid.color().lerp(Rgba::WHITE, lightening);
+                let alpha = MEMBERSHIP_MIN_ALPHA + (1. - MEMBERSHIP_MIN_ALPHA) * membership;
+                let scale = MEMBERSHIP_MIN_SCALE + (1. - MEMBERSHIP_MIN_SCALE) * membership;
+                batches[boid.species() % batch_count].push(
+                    boid.get_draw_param()
+                        .color(Color::from(Rgba {
+                            a: color.a * alpha,
+                            ..color
+                        }))
+                        .scale(Vec2::splat(scale)),
                 );
-                // If there are no close boids, return 0:
-                if count == 1 {
-                    return Vec2::ZERO;
+            }
+        }
+
+        // Draw the trails below everything else, as a faint heatmap relative to the strongest trail:
+        let strongest = sim.trail_cells().map(|(_, trail)| trail).fold(0., f32::max);
+        if strongest > 0. {
+            let mut builder = MeshBuilder::new();
+            for (cell, trail) in sim.trail_cells().filter(|(_, trail)| *trail > 0.) {
+                let alpha = 0.3 * trail / strongest;
+                builder.rectangle(DrawMode::fill(), cell, Color::new(1., 0.8, 0.2, alpha))?;
+            }
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
+
+        // Draw the calm zones and the reefs below everything except trails:
+        for reef in sim.reefs() {
+            canvas.draw(
+                &Mesh::new_circle(
+                    ctx,
+                    DrawMode::fill(),
+                    reef.pos,
+                    reef.radius,
+                    1.,
+                    Color::new(0., 0.8, 0., 0.15),
+                )?,
+                DrawParam::default(),
+            );
+        }
+        for calm_zone in sim.calm_zones() {
+            canvas.draw(
+                &Mesh::new_circle(
+                    ctx,
+                    DrawMode::fill(),
+                    calm_zone.pos,
+                    calm_zone.radius,
+                    1.,
+                    Color::new(0., 0., 1., 0.15),
+                )?,
+                DrawParam::default(),
+            );
+        }
+
+        // Draw the time lens faintly:
+        if let Some(center) = sim.time_lens() {
+            canvas.draw(
+                &Mesh::new_circle(
+                    ctx,
+                    DrawMode::stroke(1.),
+                    center,
+                    sim.config().time_lens_radius,
+                    1.,
+                    Color::new(1., 1., 1., 0.25),
+                )?,
+                DrawParam::default(),
+            );
+        }
+
+        // Draw the boundary's outline:
+        if let Some(boundary) = sim.boundary() {
+            canvas.draw(
+                &Mesh::new_polygon(
+                    ctx,
+                    DrawMode::stroke(2.),
+                    &boundary.points,
+                    Color::from_rgb(128, 128, 128),
+                )?,
+                DrawParam::default(),
+            );
+        }
+
+        // Draw the hulls below the boids:
+        let hulls = match self.hulls {
+            Hulls::None => Vec::new(),
+            Hulls::Flock => vec![(sim.flock_hull(), Rgba::new(0.5, 0.5, 0.5, 1.))],
+            Hulls::Groups => sim.group_hulls(),
+        };
+        for (hull, color) in hulls.iter().filter(|(hull, _)| hull.len() >= 3) {
+            let color = Rgba {
+                a: HULL_ALPHA,
+                ..color.lerp(Rgba::WHITE, lightening)
+            };
+            canvas.draw(
+                &Mesh::new_polygon(ctx, DrawMode::fill(), hull, Color::from(color))?,
+                DrawParam::default(),
+            );
+        }
+
+        // Draw the obstacles:
+        for obstacle in sim.obstacles() {
+            canvas.draw(
+                &Mesh::new_circle(
+                    ctx,
+                    DrawMode::fill(),
+                    obstacle.pos,
+                    obstacle.radius,
+                    1.,
+                    Color::from_rgb(128, 128, 128),
+                )?,
+                DrawParam::default(),
+            );
+        }
+
+        // Draw the influencers, attractors as green pluses and repellers as red minuses:
+        if !sim.influencers().is_empty() {
+            let mut builder = MeshBuilder::new();
+            let (horizontal, vertical) = (Vec2::new(8., 0.), Vec2::new(0., 8.));
+            for influencer in sim.influencers() {
+                let pos = influencer.pos;
+                if influencer.strength > 0. {
+                    builder.line(&[pos - horizontal, pos + horizontal], 3., Color::GREEN)?;
+                    builder.line(&[pos - vertical, pos + vertical], 3., Color::GREEN)?;
+                } else {
+                    builder.line(&[pos - horizontal, pos + horizontal], 3., Color::RED)?;
                 }
-                // Return the difference between the average direction and the boid's direction:
-                ALIGNMENT_FACTOR * (sum * (count as f32).recip() - this.boid.speed())
-            })
-            .collect()
-    }
+            }
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
 
-    /// According to boids' rule of cohesion, returns a vector containing the difference between
-    /// each boid's current position and the average position of close boids who share its color.
-    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
-    /// vector.
-    fn calc_cohesion_directions(&self) -> Vec<Vec2> {
-        self.boids
-            .iter()
-            .map(|this| {
-                // Initialize sum and counter:
-                let mut sum = Vec2::ZERO;
-                let mut count = 0usize;
-
-                // Calculate the average direction of nearby boids:
-                run_for_neighbor_cells(
-                    this.row,
-                    this.col,
-                    LOCATION_GRID_WIDTH,
-                    LOCATION_GRID_HEIGHT,
-                    |row, col| {
-                        for other_idx in &self.location_grid[row][col] {
-                            // Check that the distance between boids is within the influence radius:
-                            let other = &self.boids[*other_idx];
-                            if this.boid.pos().distance_squared(other.boid.pos())
-                                > INFLUENCE_DISTANCE_SQUARED
-                            {
-                                continue;
-                            }
-                            // Check if they have different colors:
-                            else if this.boid.color() != other.boid.color() {
-                                continue;
-                            }
-
-                            // Add current position to average (this includes our position):
-                            sum += other.boid.pos();
-                            count += 1;
-                        }
-                    },
-                );
+        // Draw the tether from the grabbed boids to the cursor:
+        if let Some(anchor) = sim.tether_anchor() {
+            let mut builder = MeshBuilder::new();
+            for boid in sim.tethered_boids() {
+                builder.line(&[boid.pos(), anchor], 1., Color::from_rgb(255, 128, 0))?;
+            }
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
+
+        // Draw a circle around the leader, or around every group's leader in the group's color (or
+        // in red around leaders the boids flee from):
+        let leaders: Vec<(&Boid, Color)> = match sim.config().leader_mode {
+            LeaderMode::Single => sim
+                .leader()
+                .map(|leader| (leader, Color::YELLOW))
+                .into_iter()
+                .collect(),
+            LeaderMode::PerGroup => sim
+                .group_leaders()
+                .map(|leader| (leader, Color::from(leader.color())))
+                .collect(),
+        };
+        for (leader, color) in leaders {
+            let color = if sim.leader_repels() {
+                Color::RED
+            } else {
+                color
+            };
+            canvas.draw(
+                &Mesh::new_circle(ctx, DrawMode::stroke(5.), leader.pos(), 30., 1., color)?,
+                DrawParam::default(),
+            );
+        }
+
+        // Draw a circle around every selected boid:
+        if sim.selected_boids().next().is_some() {
+            let mut builder = MeshBuilder::new();
+            for boid in sim.selected_boids() {
+                builder.circle(DrawMode::stroke(1.), boid.pos(), 8., 1., Color::CYAN)?;
+            }
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
 
-                // If there are no close boids, return 0:
-                if count == 1 {
-                    return Vec2::ZERO;
+        // Draw a halo around every boid, more opaque the more the boid influences its neighbors:
+        if self.show_influence {
+            Self::draw_glow(ctx, canvas, sim, visible, lightening)?;
+        }
+
+        // Draw the flow lines:
+        if self.show_flow_lines {
+            Self::draw_flow_lines(ctx, canvas, sim, visible, lightening)?;
+        }
+
+        // Draw the boids' mesh with the drawing parameters, batch by batch. Instancing draws a whole
+        // batch at once, but misbehaves on some older GPUs so each boid can be drawn on its own
+        // instead. If no boid is visible (or there are no boids at all), there's nothing to draw:
+        if batches.iter().any(|batch| !batch.is_empty()) {
+            let mesh = Boid::get_boid_mesh(ctx)?;
+            for draw_params in batches.into_iter().filter(|batch| !batch.is_empty()) {
+                if self.config.instanced_rendering {
+                    let mut draw_params_arr = InstanceArray::new(ctx, None);
+                    draw_params_arr.set(draw_params);
+                    canvas.draw_instanced_mesh(
+                        mesh.clone(),
+                        &draw_params_arr,
+                        DrawParam::default(),
+                    );
+                } else {
+                    for draw_param in draw_params {
+                        canvas.draw(&mesh, draw_param);
+                    }
                 }
+            }
+        }
 
-                // Return the difference between the average position and the boid's position:
-                COHESION_FACTOR * (sum * (count as f32).recip() - this.boid.pos())
-            })
-            .collect()
+        // Draw the predators (there are only a few, so they're drawn one by one):
+        if !sim.predators().is_empty() {
+            let mesh = Boid::get_boid_mesh(ctx)?;
+            for predator in sim.predators() {
+                canvas.draw(&mesh, predator.get_draw_param());
+            }
+        }
+        // Draw the velocities:
+        if self.show_velocities {
+            Self::draw_velocities(ctx, canvas, sim, lightening)?;
+        }
+        // Draw the directions the rules gave the traced boid:
+        if let Some((boid, trace)) = sim.traced_boid() {
+            Self::draw_rule_arrows(ctx, canvas, boid.pos, &trace)?;
+        }
+
+        // Draw the target:
+        if let Some(target_pos) = sim.target() {
+            let target_circle =
+                Mesh::new_circle(ctx, DrawMode::fill(), target_pos, 10., 1., Color::RED)?;
+            canvas.draw(&target_circle, DrawParam::default());
+        }
+
+        // Draw the selection box while it's dragged:
+        if let Some((_, corner, opposite)) = self.selecting.filter(|(i, ..)| *i == index) {
+            canvas.draw(
+                &Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::stroke(1.),
+                    rect_between(corner, opposite),
+                    Color::CYAN,
+                )?,
+                DrawParam::default(),
+            );
+        }
+
+        // Highlight the inspected grid cell, the boids in it and the cells scanned around it:
+        if let Some((_, point)) = self.inspected.filter(|(i, _)| *i == index) {
+            if let Some(inspection) = sim.inspect_cell(point) {
+                let mut builder = MeshBuilder::new();
+                for &(row, col) in inspection.neighborhood.iter() {
+                    let cell = Simulation::cell_rect(row, col);
+                    builder.rectangle(DrawMode::stroke(1.), cell, Color::YELLOW)?;
+                }
+                let cell = Simulation::cell_rect(inspection.row, inspection.col);
+                builder.rectangle(DrawMode::fill(), cell, Color::new(1., 1., 0., 0.2))?;
+                let positions: Vec<Vec2> = sim.boids().map(|boid| boid.pos()).collect();
+                for &idx in inspection.boids.iter() {
+                    builder.circle(DrawMode::stroke(1.), positions[idx], 8., 1., Color::YELLOW)?;
+                }
+                canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+            }
+        }
+
+        // Draw the grid's occupancy on top of everything:
+        if self.show_occupancy {
+            Self::draw_occupancy(ctx, canvas, sim, view)?;
+        }
+
+        Ok(())
     }
 
-    /// According to boids' rule of evasion, returns a vector of directions that avoid obstacles.
-    /// Obstacles are currently just walls, but may be more later.
-    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
-    /// vector.
-    fn calc_evasion_directions(&self) -> Vec<Vec2> {
-        self.boids
+    /// Draws information about the simulation on top of it, and the borders between regions.
+    fn draw_hud(&self, ctx: &Context, canvas: &mut Canvas) -> GameResult {
+        // Draw the HUD in screen coordinates, regardless of the cameras:
+        canvas.set_default_scissor_rect();
+        canvas.set_screen_coordinates(Rect::new(0., 0., SCREEN_WIDTH, SCREEN_HEIGHT));
+
+        if self.regions.len() > 1 {
+            let mut builder = MeshBuilder::new();
+            for region in self.regions.iter() {
+                builder.rectangle(DrawMode::stroke(2.), region.bounds, Color::BLACK)?;
+            }
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
+
+        let mut lines = vec![(
+            format!("Spawning species {}", self.spawn_color_idx + 1),
+            Color::from(BOID_COLORS[self.spawn_color_idx]),
+        )];
+        if self.paused {
+            let step = match self.timeline {
+                Some(index) => self.regions[0].history[index].step_count(),
+                None => self.regions[0].sim.step_count(),
+            };
+            lines.push((format!("Paused at step {step}"), Color::BLUE));
+        }
+
+        // Show when the simulation falls behind real time:
+        if self.dropped_steps > 0 {
+            lines.push((format!("Dropped {} steps", self.dropped_steps), Color::RED));
+        }
+
+        // The grid only matches if it matches in every region:
+        let checks: Vec<bool> = self
+            .regions
             .iter()
-            .map(|grid_boid| {
-                // Initialize vector with no evasion:
-                let mut dir = Vec2::ZERO;
-
-                // Check floor and ceiling:
-                let pos = grid_boid.boid.pos();
-                if pos.y < MARGIN {
-                    dir.y = EVASION_FACTOR; // Go down
-                } else if pos.y > SCREEN_HEIGHT - MARGIN {
-                    dir.y = -EVASION_FACTOR; // Go up
-                }
+            .filter_map(|region| region.sim.neighbors_match())
+            .collect();
+        if !checks.is_empty() {
+            lines.push(if checks.iter().all(|&matches| matches) {
+                ("Grid matches brute force".to_string(), Color::GREEN)
+            } else {
+                ("Grid doesn't match brute force".to_string(), Color::RED)
+            });
+        }
+
+        for (i, (line, color)) in lines.into_iter().enumerate() {
+            canvas.draw(
+                Text::new(line).set_scale(HUD_TEXT_SIZE),
+                DrawParam::default()
+                    .dest(Vec2::new(10., 10. + i as f32 * HUD_TEXT_SIZE))
+                    .color(color),
+            );
+        }
 
-                // Check two walls:
-                if pos.x < MARGIN {
-                    dir.x = EVASION_FACTOR; // Go right
-                } else if pos.x > SCREEN_WIDTH - MARGIN {
-                    dir.x = -EVASION_FACTOR; // Go left
+        if self.show_frame_graph {
+            self.draw_frame_graph(ctx, canvas)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws the durations of the latest frames as a graph at the bottom left corner of the
+    /// window, with the time budget of a frame halfway up the graph. Longer frames are cut off at
+    /// the top of the graph.
+    fn draw_frame_graph(&self, ctx: &Context, canvas: &mut Canvas) -> GameResult {
+        let [width, height] = FRAME_GRAPH_SIZE;
+        let graph = Rect::new(10., SCREEN_HEIGHT - 10. - height, width, height);
+        let budget = 1. / FPS as f32;
+
+        let mut builder = MeshBuilder::new();
+        builder.rectangle(DrawMode::fill(), graph, Color::new(0., 0., 0., 0.5))?;
+        let budget_y = graph.y + height / 2.;
+        builder.line(
+            &[
+                Vec2::new(graph.x, budget_y),
+                Vec2::new(graph.x + width, budget_y),
+            ],
+            1.,
+            Color::GREEN,
+        )?;
+
+        // A line needs at least two points:
+        if self.frame_times.len() >= 2 {
+            let step = width / (FRAME_GRAPH_LENGTH - 1) as f32;
+            let points: Vec<Vec2> = self
+                .frame_times
+                .iter()
+                .enumerate()
+                .map(|(i, &frame_time)| {
+                    let y = (frame_time / (2. * budget)).min(1.) * height;
+                    Vec2::new(graph.x + i as f32 * step, graph.bottom() - y)
+                })
+                .collect();
+            builder.line(&points, 1., Color::WHITE)?;
+        }
+
+        canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        Ok(())
+    }
+
+    /// Reloads the main configuration file and the regions' configuration files, keeping the
+    /// current configuration of every file that failed to load. The number of regions can't change
+    /// while the simulation is running.
+    fn reload_configs(&mut self) {
+        // The scenario's parameters replace the main configuration file:
+        let (path, loaded) = match &self.scenario_path {
+            Some(path) => (
+                path.as_str(),
+                Scenario::load(path).map(|scenario| scenario.config),
+            ),
+            None => (CONFIG_PATH, SimConfig::load(CONFIG_PATH)),
+        };
+        match loaded {
+            Ok(mut config) => {
+                // The window is only split into regions when the simulation starts:
+                let layout = (self.config.region_columns, self.config.region_rows);
+                if (config.region_columns, config.region_rows) != layout {
+                    eprintln!("{path}: region_columns and region_rows only change on restart");
+                    (config.region_columns, config.region_rows) = layout;
                 }
+                self.config = config;
+                println!("Reloaded {path}");
+            }
+            Err(err) => {
+                eprintln!("{path}: {err}");
+                return;
+            }
+        }
 
-                // Return final direction:
-                dir
-            })
-            .collect()
+        // Regions whose configuration file is broken keep their current configuration:
+        for i in 0..self.regions.len() {
+            if let Some(config) = Self::load_region_config(&self.config, i) {
+                self.regions[i].sim.set_config(config);
+            }
+        }
+    }
+
+    /// Pauses or resumes the simulation. If a snapshot from the timeline is shown when resuming,
+    /// the simulation continues from it.
+    fn toggle_pause(&mut self) {
+        if let Some(index) = self.timeline.take() {
+            for region in self.regions.iter_mut() {
+                region.rewind_to(index);
+            }
+        }
+        self.paused = !self.paused;
+    }
+
+    /// Moves through the timeline while paused, going back in time if `back` is set and forward
+    /// otherwise. Going forward from the newest snapshot returns to the current state.
+    fn scrub(&mut self, back: bool) {
+        let snapshots = self.regions[0].history.len();
+        if !self.paused || snapshots == 0 {
+            return;
+        }
+        self.timeline = match (self.timeline, back) {
+            (None, true) => Some(snapshots - 1),
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < snapshots => Some(index + 1),
+            _ => None,
+        };
+    }
+
+    /// Starts recording statistics to a new CSV file, or stops recording if it's already on.
+    fn toggle_recording(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            match recorder.flush() {
+                Ok(()) => println!("Stopped recording"),
+                Err(err) => eprintln!("Couldn't save the recording: {err}"),
+            }
+            return;
+        }
+
+        let path = format!("boids_stats_{}.csv", timestamp());
+        match StatsRecorder::create(&path) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                println!("Recording to {path}");
+            }
+            Err(err) => eprintln!("Couldn't create {path}: {err}"),
+        }
     }
 
-    /// Recalculates the indices of the boids inside the grid.
-    fn recalculate_boid_indices(&mut self) {
-        // For each boid:
-        self.boids
+    /// Records the statistics of every region if recording is on and the last step is at the start
+    /// of an interval. If writing fails, recording stops.
+    fn record_stats(&mut self) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        for (i, region) in self.regions.iter().enumerate() {
+            if region.sim.step_count() % region.sim.config().stats_interval != 0 {
+                continue;
+            }
+            if let Err(err) = recorder.record(i, &region.sim) {
+                eprintln!("Couldn't record statistics: {err}");
+                self.recorder = None;
+                return;
+            }
+        }
+    }
+
+    /// Starts tracing the selected boid to a new CSV file, or stops tracing if it's already on.
+    /// The first region with exactly one selected boid is traced.
+    fn toggle_trace(&mut self) {
+        if let Some((i, recorder)) = self.tracer.take() {
+            self.regions[i].sim.stop_trace();
+            Self::stop_trace(recorder);
+            return;
+        }
+
+        let Some(i) = self
+            .regions
             .iter_mut()
-            .enumerate()
-            .for_each(|(i, grid_boid)| {
-                // Calculate new indices:
-                let pos = grid_boid.boid.pos();
-                let (row, col) = (
-                    (pos.y / INFLUENCE_DISTANCE) as usize,
-                    (pos.x / INFLUENCE_DISTANCE) as usize,
-                );
+            .position(|region| region.sim.trace_selection())
+        else {
+            println!("Select a single boid to trace it");
+            return;
+        };
+        let path = format!("boids_trace_{}.csv", timestamp());
+        match TraceRecorder::create(&path) {
+            Ok(recorder) => {
+                self.tracer = Some((i, recorder));
+                println!("Tracing to {path}");
+            }
+            Err(err) => {
+                self.regions[i].sim.stop_trace();
+                eprintln!("Couldn't create {path}: {err}");
+            }
+        }
+    }
+
+    /// Saves the trace in the given recorder.
+    fn stop_trace(mut recorder: TraceRecorder) {
+        match recorder.flush() {
+            Ok(()) => println!("Stopped tracing"),
+            Err(err) => eprintln!("Couldn't save the trace: {err}"),
+        }
+    }
 
-                // Remove the current index from the outdated grid cell:
-                self.location_grid[grid_boid.row][grid_boid.col].remove(&i);
+    /// Records the traced boid if tracing is on. Tracing stops once the boid is no longer traced
+    /// (i.e: it was deselected or removed), or if writing fails.
+    fn record_trace(&mut self) {
+        let Some((i, recorder)) = self.tracer.as_mut() else {
+            return;
+        };
+        let sim = &self.regions[*i].sim;
+        if !sim.is_tracing() {
+            if let Some((_, recorder)) = self.tracer.take() {
+                Self::stop_trace(recorder);
+            }
+            return;
+        }
+        if let Err(err) = recorder.record(sim) {
+            eprintln!("Couldn't record the trace: {err}");
+            self.tracer = None;
+        }
+    }
 
-                // Update in boid:
-                (grid_boid.row, grid_boid.col) = (row, col);
+    /// Exports the current frame of every region as an SVG image.
+    fn export_svgs(&self) {
+        let timestamp = timestamp();
+        for (i, region) in self.regions.iter().enumerate() {
+            let path = if self.regions.len() > 1 {
+                format!("boids_{timestamp}_{i}.svg")
+            } else {
+                format!("boids_{timestamp}.svg")
+            };
+            match svg::export_svg(&region.sim, &path) {
+                Ok(()) => println!("Exported {path}"),
+                Err(err) => eprintln!("Couldn't export {path}: {err}"),
+            }
+        }
+    }
 
-                // Update in location grid:
-                self.location_grid[row][col].insert(i);
-            });
+    /// Saves the current configuration of every region as a TOML file (which can be loaded like
+    /// the configuration file), and prints it.
+    fn save_configs(&self) {
+        let timestamp = timestamp();
+        for (i, region) in self.regions.iter().enumerate() {
+            let path = if self.regions.len() > 1 {
+                format!("boids_config_{timestamp}_{i}.toml")
+            } else {
+                format!("boids_config_{timestamp}.toml")
+            };
+            let text = region.sim.config().to_toml();
+            match text.and_then(|text| std::fs::write(&path, &text).map(|()| text)) {
+                Ok(text) => println!("{text}Saved {path}"),
+                Err(err) => eprintln!("Couldn't save {path}: {err}"),
+            }
+        }
     }
 }
 
 impl EventHandler for BoidsSim {
     fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
-        // Calculate change in time per frame:
+        // While the window is out of focus, skip the time that passed instead of simulating it (so
+        // regaining focus doesn't cause a catch up), and don't hog the CPU:
+        if self.is_asleep() {
+            while ctx.time.check_update_time(FPS) {}
+            std::thread::sleep(UNFOCUSED_FRAME_TIME);
+            return Ok(());
+        }
+
+        // Without vsync, wait out the rest of the frame so the window doesn't draw more frames than
+        // the cap:
+        if let Some(cap) = self.config.fps_cap.filter(|_| !self.config.vsync) {
+            let frame_time = Duration::from_secs_f64(1. / cap as f64);
+            if let Some(left) = frame_time.checked_sub(self.last_frame.elapsed()) {
+                std::thread::sleep(left);
+            }
+        }
+        self.last_frame = Instant::now();
+
+        // Calculate change in time per frame. If the frame took too long, only run a limited
+        // number of steps and drop the rest so the window stays responsive. While paused, all
+        // steps are dropped:
+        let mut steps = 0;
+        // While tab is held, the time lens follows the cursor in the region it's in:
+        let lens = ctx
+            .keyboard
+            .is_key_pressed(VirtualKeyCode::Tab)
+            .then(|| Vec2::from(ctx.mouse.position()));
+        for region in self.regions.iter_mut() {
+            let center = lens
+                .filter(|&cursor| region.bounds.contains(cursor))
+                .map(|cursor| region.screen_to_world(cursor));
+            region.sim.set_time_lens(center);
+        }
+        if let Some(excitement) = &self.excitement {
+            let level = f32::from_bits(excitement.load(Ordering::Relaxed));
+            for region in self.regions.iter_mut() {
+                region.sim.set_excitement(level);
+            }
+        }
         while ctx.time.check_update_time(FPS) {
-            // Update boids:
-            self.update_boids();
+            if self.paused {
+                continue;
+            }
+            if steps >= self.config.max_steps_per_frame {
+                self.dropped_steps += 1;
+                continue;
+            }
+
+            // Update boids, and save them in the timeline once every stride:
+            for region in self.regions.iter_mut() {
+                region.sim.step();
+                if region.sim.step_count() % self.config.history_stride == 0 {
+                    region.capture(self.config.history_length);
+                }
+            }
+            steps += 1;
+            self.record_stats();
+            self.record_trace();
+        }
+
+        // Time passed since the last frame:
+        let dt = ctx.time.delta().as_secs_f32();
+        self.frame_times.push_back(dt);
+        if self.frame_times.len() > FRAME_GRAPH_LENGTH {
+            self.frame_times.pop_front();
+        }
+
+        // Advance the time of day:
+        if self.day_cycle {
+            self.time_of_day = (self.time_of_day + dt / self.config.day_cycle_period).fract();
+        }
+
+        // Move the cameras:
+        for region in self.regions.iter_mut() {
+            region.update_camera(self.flock_cam, dt);
         }
 
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
-        // Get canvas:
-        let mut canvas = Canvas::from_frame(ctx, Color::WHITE);
+        // Nothing changes while the simulation is paused:
+        if self.is_asleep() {
+            return Ok(());
+        }
 
-        // Create new instance array with boids' drawing parameters:
-        let mut draw_params_arr = InstanceArray::new(ctx, None);
-        let draw_params: Vec<DrawParam> = self
-            .boids
-            .iter()
-            .map(|grid_boid| grid_boid.boid.get_draw_param())
-            .collect();
-        draw_params_arr.set(draw_params);
+        // Get canvas, colored by the time of day:
+        let (background, lightening) = day_cycle_colors(self.time_of_day);
+        let mut canvas = Canvas::from_frame(ctx, Color::from(background));
 
-        // Draw a circle around the leader:
-        if let Some(idx) = self.leader_idx {
-            canvas.draw(
-                &Mesh::new_circle(
-                    ctx,
-                    DrawMode::stroke(5.),
-                    self.boids[idx].boid.pos(),
-                    30.,
-                    1.,
-                    Color::YELLOW,
-                )?,
-                DrawParam::default(),
-            );
+        // Draw every region in its part of the window:
+        for (index, region) in self.regions.iter().enumerate() {
+            self.draw_region(ctx, &mut canvas, index, region, lightening)?;
         }
 
-        // Draw the boids' mesh with the drawing parameters:
-        canvas.draw_instanced_mesh(
-            Boid::get_boid_mesh(ctx)?,
-            &draw_params_arr,
-            DrawParam::default(),
-        );
-        // Draw the target:
-        if let Some(target_pos) = self.target {
-            let target_circle =
-                Mesh::new_circle(ctx, DrawMode::fill(), target_pos, 10., 1., Color::RED)?;
-            canvas.draw(&target_circle, DrawParam::default());
-        }
+        // Draw the HUD:
+        self.draw_hud(ctx, &mut canvas)?;
 
         // Finish the canvas:
         canvas.finish(ctx)
     }
 
     fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        // Only the region under the mouse is affected:
+        let Some(index) = self.region_at(Vec2::new(x, y)) else {
+            return Ok(());
+        };
+        let region = &mut self.regions[index];
+        let pos = region.screen_to_world(Vec2::new(x, y));
+
+        // Right clicking spawns boids:
+        if button == MouseButton::Right {
+            region
+                .sim
+                .spawn_boids(pos, SPAWN_COUNT, SPAWN_RADIUS, self.spawn_color_idx);
+            return Ok(());
+        }
+
+        // If o is held, remove the clicked obstacle or place a new one:
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::O) {
+            if !region.sim.remove_obstacles_at(pos) {
+                region.sim.add_obstacle(Obstacle::new(pos, OBSTACLE_RADIUS));
+            }
+            return Ok(());
+        }
+
+        // Control dragging selects the boids inside the dragged box:
+        if ctx.keyboard.is_mod_active(KeyMods::CTRL) {
+            self.selecting = Some((index, pos, pos));
+            return Ok(());
+        }
+
+        // Shift clicking grabs the boids around the cursor:
+        if ctx.keyboard.is_mod_active(KeyMods::SHIFT) {
+            if region.sim.grab_boids(pos, TETHER_GRAB_RADIUS) {
+                self.grabbing = Some(index);
+            }
+            return Ok(());
+        }
+
+        // If x is held, remove the boids around the cursor:
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::X) {
+            region.sim.remove_boids_near(pos, SPAWN_RADIUS);
+            return Ok(());
+        }
+
+        // If z is held, remove the clicked calm zone or place a new one:
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::Z) {
+            if !region.sim.remove_calm_zones_at(pos) {
+                region
+                    .sim
+                    .add_calm_zone(CalmZone::new(pos, CALM_ZONE_RADIUS));
+            }
+            return Ok(());
+        }
+
+        // If j is held, remove the clicked reef or place a new one:
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::J) {
+            if !region.sim.remove_reefs_at(pos) {
+                region.sim.add_reef(Reef::new(pos, REEF_RADIUS));
+            }
+            return Ok(());
+        }
+
+        // If m is held, inspect the clicked grid cell (or stop inspecting it if it's clicked again):
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::M) {
+            let Some(inspection) = region.sim.inspect_cell(pos) else {
+                return Ok(());
+            };
+            let same_cell = self.inspected.is_some_and(|(i, point)| {
+                i == index
+                    && region.sim.inspect_cell(point).is_some_and(|inspected| {
+                        (inspected.row, inspected.col) == (inspection.row, inspection.col)
+                    })
+            });
+            if same_cell {
+                self.inspected = None;
+            } else {
+                println!(
+                    "Cell ({}, {}) at ({:.1}, {:.1}): boids {:?}, scanned cells {:?}",
+                    inspection.row,
+                    inspection.col,
+                    pos.x,
+                    pos.y,
+                    inspection.boids,
+                    inspection.neighborhood
+                );
+                self.inspected = Some((index, pos));
+            }
+            return Ok(());
+        }
+
+        // If p is held, remove the clicked predator or place a new one:
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::P) {
+            if !region
+                .sim
+                .remove_predators_near(pos, PREDATOR_SELECT_DISTANCE)
+            {
+                region.sim.add_predator(pos);
+            }
+            return Ok(());
+        }
+
+        // If a or q is held, remove the clicked influencer or place a new attractor or repeller:
+        let attract = ctx.keyboard.is_key_pressed(VirtualKeyCode::A);
+        if attract || ctx.keyboard.is_key_pressed(VirtualKeyCode::Q) {
+            if !region
+                .sim
+                .remove_influencers_near(pos, INFLUENCER_SELECT_DISTANCE)
+            {
+                let strength = if attract {
+                    INFLUENCER_STRENGTH
+                } else {
+                    -INFLUENCER_STRENGTH
+                };
+                region.sim.add_influencer(Influencer::new(pos, strength));
+            }
+            return Ok(());
+        }
+
+        // Set the target as the pressed location, and keep moving it while the button is held:
+        region.sim.set_target(Some(pos));
+        self.dragging_target = Some(index);
+
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
         &mut self,
         _ctx: &mut Context,
         _button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> Result<(), GameError> {
+        // Stop dragging the target, let go of the grabbed boids and select the boids in the box:
+        self.dragging_target = None;
+        if let Some(index) = self.grabbing.take() {
+            self.regions[index].sim.release_tether();
+        }
+        if let Some((index, corner, opposite)) = self.selecting.take() {
+            self.regions[index]
+                .sim
+                .select_boids(rect_between(corner, opposite));
+        }
+
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
         x: f32,
         y: f32,
+        dx: f32,
+        dy: f32,
     ) -> Result<(), GameError> {
-        // Set the target as the pressed location:
-        self.target = Some(Vec2::new(x, y));
+        // Move the target with the mouse while it's dragged:
+        if let Some(index) = self.dragging_target {
+            let region = &mut self.regions[index];
+            let pos = region.screen_to_world(Vec2::new(x, y));
+            region.sim.set_target(Some(pos));
+        }
+
+        // Stretch the selection box to the mouse:
+        if let Some((index, _, opposite)) = &mut self.selecting {
+            *opposite = self.regions[*index].screen_to_world(Vec2::new(x, y));
+        }
+
+        // Pull the grabbed boids after the mouse:
+        if let Some(index) = self.grabbing {
+            let region = &mut self.regions[index];
+            let pos = region.screen_to_world(Vec2::new(x, y));
+            region.sim.move_tether(pos);
+        }
+
+        // If g is held, swiping the mouse blows a gust along the swipe:
+        if ctx.keyboard.is_key_pressed(VirtualKeyCode::G) {
+            if let Some(index) = self.region_at(Vec2::new(x, y)) {
+                let region = &mut self.regions[index];
+                let from = region.screen_to_world(Vec2::new(x - dx, y - dy));
+                let to = region.screen_to_world(Vec2::new(x, y));
+                region.sim.apply_gust(from, to);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> Result<(), GameError> {
+        // Stop the sprinting boid once h is released:
+        if input.keycode == Some(VirtualKeyCode::H) {
+            if let Some(index) = self.sprinting.take() {
+                self.regions[index].sim.stop_sprint();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> Result<bool, GameError> {
+        // Don't lose the end of the recording:
+        if let Some(mut recorder) = self.recorder.take() {
+            if let Err(err) = recorder.flush() {
+                eprintln!("Couldn't save the recording: {err}");
+            }
+        }
+        if let Some((_, recorder)) = self.tracer.take() {
+            Self::stop_trace(recorder);
+        }
+
+        if self.config.save_session_on_quit {
+            let session = Session::capture(self.regions.iter().map(|region| &region.sim));
+            match session.save(SESSION_PATH) {
+                Ok(()) => println!("Saved the session to {SESSION_PATH}"),
+                Err(err) => eprintln!("Couldn't save {SESSION_PATH}: {err}"),
+            }
+        }
+
+        // Quit:
+        Ok(false)
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) -> Result<(), GameError> {
+        self.focused = gained;
 
         Ok(())
     }
 
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         input: KeyInput,
-        _repeated: bool,
+        repeated: bool,
     ) -> Result<(), GameError> {
         if let Some(keycode) = input.keycode {
             match keycode {
-                // If the user pressed space, delete target:
+                // If the user pressed h, sprint the boid under the cursor until h is released:
+                VirtualKeyCode::H if !repeated => {
+                    let cursor = Vec2::from(ctx.mouse.position());
+                    if let Some(index) = self.region_at(cursor) {
+                        let region = &mut self.regions[index];
+                        let pos = region.screen_to_world(cursor);
+                        if region.sim.start_sprint(pos, SPRINT_HOVER_RADIUS) {
+                            self.sprinting = Some(index);
+                        }
+                    }
+                }
+                // If the user pressed space, delete the targets:
                 VirtualKeyCode::Space => {
-                    self.target = None;
+                    for region in self.regions.iter_mut() {
+                        region.sim.set_target(None);
+                    }
+                }
+                // If the user pressed an arrow key, nudge the targets:
+                VirtualKeyCode::Up
+                | VirtualKeyCode::Down
+                | VirtualKeyCode::Left
+                | VirtualKeyCode::Right => {
+                    let nudge = match keycode {
+                        VirtualKeyCode::Up => Vec2::NEG_Y,
+                        VirtualKeyCode::Down => Vec2::Y,
+                        VirtualKeyCode::Left => Vec2::NEG_X,
+                        _ => Vec2::X,
+                    };
+                    for region in self.regions.iter_mut() {
+                        if let Some(target_pos) = region.sim.target() {
+                            region
+                                .sim
+                                .set_target(Some(target_pos + TARGET_NUDGE * nudge));
+                        }
+                    }
                 }
                 // If the user pressed w, toggle walls:
                 VirtualKeyCode::W => {
-                    self.restrict_walls = !self.restrict_walls;
+                    for region in self.regions.iter_mut() {
+                        region.sim.set_restrict_walls(!region.sim.restrict_walls());
+                    }
+                }
+                // If the user pressed F5, reload the configuration files (keeping the current ones
+                // if they failed):
+                VirtualKeyCode::F5 => self.reload_configs(),
+                // If the user pressed F6, save the current configurations:
+                VirtualKeyCode::F6 => self.save_configs(),
+                // If the user pressed e, export the current frame as SVG images:
+                VirtualKeyCode::E => self.export_svgs(),
+                // If the user pressed v, toggle drawing velocities:
+                VirtualKeyCode::V => {
+                    self.show_velocities = !self.show_velocities;
+                }
+                // If the user pressed i, toggle drawing flow lines instead of boids:
+                VirtualKeyCode::I => {
+                    self.show_flow_lines = !self.show_flow_lines;
+                }
+                // If the user pressed shift+y, toggle the halos around influential boids:
+                VirtualKeyCode::Y if ctx.keyboard.is_mod_active(KeyMods::SHIFT) => {
+                    self.show_influence = !self.show_influence;
+                }
+                // If the user pressed y, toggle drawing boids by how many close boids they have:
+                VirtualKeyCode::Y => {
+                    self.show_membership = !self.show_membership;
+                }
+                // If the user pressed d, toggle showing the grid's occupancy:
+                VirtualKeyCode::D => {
+                    self.show_occupancy = !self.show_occupancy;
+                }
+                // If the user pressed u, switch to the next kind of hulls (none, the flock's or the
+                // groups'):
+                VirtualKeyCode::U => {
+                    self.hulls = match self.hulls {
+                        Hulls::None => Hulls::Flock,
+                        Hulls::Flock => Hulls::Groups,
+                        Hulls::Groups => Hulls::None,
+                    };
+                }
+                // If the user pressed f, toggle the frame time graph:
+                VirtualKeyCode::F => {
+                    self.show_frame_graph = !self.show_frame_graph;
+                }
+                // If the user pressed enter, pause or resume the simulation:
+                VirtualKeyCode::Return => self.toggle_pause(),
+                // If the user pressed , or . while paused, go back or forward in the timeline:
+                VirtualKeyCode::Comma => self.scrub(true),
+                VirtualKeyCode::Period => self.scrub(false),
+                // If the user pressed r, start or stop recording statistics:
+                VirtualKeyCode::R => self.toggle_recording(),
+                // If the user pressed k, start or stop tracing the selected boid:
+                VirtualKeyCode::K => self.toggle_trace(),
+                // If the user pressed t, pause or resume the day cycle:
+                VirtualKeyCode::T => {
+                    self.day_cycle = !self.day_cycle;
+                }
+                // If the user pressed n, jump to the next phase of the day (dawn, noon, dusk or
+                // midnight):
+                VirtualKeyCode::N => {
+                    self.time_of_day = ((self.time_of_day * 4.).floor() + 1.) / 4. % 1.;
+                }
+                // If the user pressed a number key, spawn boids of the species with that number:
+                VirtualKeyCode::Key1
+                | VirtualKeyCode::Key2
+                | VirtualKeyCode::Key3
+                | VirtualKeyCode::Key4
+                | VirtualKeyCode::Key5
+                | VirtualKeyCode::Key6
+                | VirtualKeyCode::Key7
+                | VirtualKeyCode::Key8
+                | VirtualKeyCode::Key9 => {
+                    let index = keycode as usize - VirtualKeyCode::Key1 as usize;
+                    if index < BOID_COLORS.len() {
+                        self.spawn_color_idx = index;
+                    }
+                }
+                // If the user pressed b, recolor the selected boids to the spawned species:
+                VirtualKeyCode::B => {
+                    for region in self.regions.iter_mut() {
+                        region.sim.recolor_selection(self.spawn_color_idx);
+                    }
+                }
+                // If the user pressed s, split the flocks in two:
+                VirtualKeyCode::S => {
+                    for region in self.regions.iter_mut() {
+                        region.sim.split_flock();
+                    }
+                }
+                // If the user pressed c, toggle the flock cam:
+                VirtualKeyCode::C => {
+                    self.flock_cam = !self.flock_cam;
+                }
+                // If the user pressed shift+l, switch between following the leaders and fleeing
+                // from them (in the regions that have leaders):
+                VirtualKeyCode::L if ctx.keyboard.is_mod_active(KeyMods::SHIFT) => {
+                    for region in self.regions.iter_mut() {
+                        if region.sim.leader_idx().is_some() {
+                            region.sim.set_leader_repels(!region.sim.leader_repels());
+                        }
+                    }
                 }
                 // If the user pressed l, toggle leader index:
                 VirtualKeyCode::L => {
-                    if let Some(_) = self.leader_idx {
-                        self.leader_idx = None;
-                    } else {
-                        self.leader_idx = Some(0);
+                    for region in self.regions.iter_mut() {
+                        if region.sim.leader_idx().is_some() {
+                            region.sim.set_leader_idx(None);
+                        } else {
+                            region.sim.set_leader_idx(Some(0));
+                        }
                     }
                 }
                 _ => {}
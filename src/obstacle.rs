@@ -0,0 +1,152 @@
+use crate::constants::INFLUENCER_FALLOFF;
+use ggez::glam::Vec2;
+
+/// A static circle in the simulation that boids avoid and can't pass through.
+#[derive(Clone, Copy)]
+pub struct Obstacle {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+impl Obstacle {
+    pub fn new(pos: Vec2, radius: f32) -> Self {
+        Self { pos, radius }
+    }
+
+    /// Returns whether the given point is inside the obstacle.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.pos.distance_squared(point) < self.radius * self.radius
+    }
+}
+
+/// A circle in the simulation where the flocking rules are weakened, so boids inside it mostly
+/// coast in straight lines.
+#[derive(Clone, Copy)]
+pub struct CalmZone {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+impl CalmZone {
+    pub fn new(pos: Vec2, radius: f32) -> Self {
+        Self { pos, radius }
+    }
+
+    /// Returns whether the given point is inside the zone.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.pos.distance_squared(point) < self.radius * self.radius
+    }
+}
+
+/// A point in the simulation steering every boid towards it (with a positive strength) or away from
+/// it (with a negative strength). Together, influencers make a field the whole flock flows along.
+#[derive(Clone, Copy)]
+pub struct Influencer {
+    pub pos: Vec2,
+    pub strength: f32,
+}
+
+impl Influencer {
+    pub fn new(pos: Vec2, strength: f32) -> Self {
+        Self { pos, strength }
+    }
+
+    /// Returns the steering the influencer applies to a boid at the given point, which gets weaker
+    /// the farther the boid is (see INFLUENCER_FALLOFF).
+    pub fn steering(&self, point: Vec2) -> Vec2 {
+        let offset = self.pos - point;
+        let falloff =
+            1. / (1. + offset.length_squared() / (INFLUENCER_FALLOFF * INFLUENCER_FALLOFF));
+        self.strength * falloff * offset.normalize_or_zero()
+    }
+}
+
+/// A circle in the simulation that pulls the boids inside it towards its center and slows them down
+/// the closer they get, so they loiter around it (like fish around a reef) instead of passing by.
+#[derive(Clone, Copy)]
+pub struct Reef {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+impl Reef {
+    pub fn new(pos: Vec2, radius: f32) -> Self {
+        Self { pos, radius }
+    }
+
+    /// Returns whether the given point is inside the reef.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.pos.distance_squared(point) < self.radius * self.radius
+    }
+
+    /// Returns how close the given point is to the reef's center, from 0 at its edge (and outside
+    /// it) to 1 at the center.
+    pub fn closeness(&self, point: Vec2) -> f32 {
+        if !self.contains(point) {
+            return 0.;
+        }
+        1. - self.pos.distance(point) / self.radius
+    }
+}
+
+/// A polygon the boids are confined to instead of the whole area, e.g: a round tank or an L-shaped
+/// room. The polygon may be concave, as long as its edges don't cross each other.
+#[derive(Clone)]
+pub struct Boundary {
+    pub points: Vec<Vec2>,
+}
+
+impl Boundary {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        Self { points }
+    }
+
+    /// Returns the edges of the polygon, as the points at their ends (the last point connects back
+    /// to the first).
+    pub fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let next = self.points.iter().cycle().skip(1);
+        self.points.iter().copied().zip(next.copied())
+    }
+
+    /// Returns whether the given point is inside the polygon (by the even-odd rule).
+    pub fn contains(&self, point: Vec2) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a.y > point.y) != (b.y > point.y) {
+                let crossing = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if point.x < crossing {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    /// Returns the direction (of length 1) a boid at the given point should steer in to stay
+    /// inside the polygon, or `None` if all edges are farther than `margin` from it. Every edge up
+    /// to the margin away pushes the boid away from the edge's closest point to it (rather than
+    /// along the edge's normal), so near a concave corner the boid isn't pushed from one edge into
+    /// the other. A boid outside the polygon is steered towards the closest edge instead.
+    pub fn evasion(&self, point: Vec2, margin: f32) -> Option<Vec2> {
+        let closest_points = self.edges().map(|(a, b)| {
+            let edge = b - a;
+            if edge == Vec2::ZERO {
+                return a;
+            }
+            a + ((point - a).dot(edge) / edge.length_squared()).clamp(0., 1.) * edge
+        });
+
+        if !self.contains(point) {
+            let closest = closest_points.min_by(|a, b| {
+                a.distance_squared(point)
+                    .total_cmp(&b.distance_squared(point))
+            })?;
+            return (closest - point).try_normalize();
+        }
+        closest_points
+            .filter(|closest| closest.distance_squared(point) < margin * margin)
+            .map(|closest| (point - closest).normalize_or_zero())
+            .sum::<Vec2>()
+            .try_normalize()
+    }
+}
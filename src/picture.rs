@@ -0,0 +1,67 @@
+use crate::constants::{BOID_COLORS, PICTURE_BOIDS, PICTURE_COLOR_TOLERANCE, PICTURE_FILL};
+use crate::simulation::Simulation;
+use ggez::glam::{Vec2, Vec3};
+use image::{Rgba as Pixel, RgbaImage};
+
+/// Replaces the boids of the given simulation with up to PICTURE_BOIDS boids drawing the given
+/// picture, which then dissolves as they start flocking.
+pub fn place_picture(sim: &mut Simulation, picture: &RgbaImage) {
+    let max_boids = PICTURE_BOIDS.min(sim.config().max_boids);
+    let boids = picture_boids(picture, sim.size(), max_boids);
+    sim.place_boids(&boids);
+}
+
+/// Returns the positions and species of boids drawing the given picture in an area of the given
+/// size: a boid for a sample of the picture's pixels that aren't background, in the species whose
+/// color is the closest to the pixel's. The picture is scaled to fit the middle of the area, and
+/// sampled sparsely enough to draw it with at most the given number of boids.
+/// The background is the color of the picture's top left pixel, and transparent pixels.
+fn picture_boids(picture: &RgbaImage, size: Vec2, max_boids: usize) -> Vec<(Vec2, usize)> {
+    let (width, height) = picture.dimensions();
+    if width == 0 || height == 0 || max_boids == 0 {
+        return Vec::new();
+    }
+    let background = rgb(picture.get_pixel(0, 0));
+    let is_foreground = |pixel: &Pixel<u8>| {
+        pixel.0[3] >= 128 && rgb(pixel).distance(background) > PICTURE_COLOR_TOLERANCE
+    };
+
+    // Only every stride-th pixel on each axis is sampled, so a picture with more foreground pixels
+    // than boids is drawn by a grid of them:
+    let foreground = picture
+        .pixels()
+        .filter(|pixel| is_foreground(pixel))
+        .count();
+    let stride = ((foreground as f32 / max_boids as f32).sqrt().ceil() as u32).max(1);
+
+    // Scale the picture to fill the middle of the area (keeping its proportions):
+    let scale = PICTURE_FILL * (size.x / width as f32).min(size.y / height as f32);
+    let offset = (size - scale * Vec2::new(width as f32, height as f32)) / 2.;
+
+    let mut boids: Vec<(Vec2, usize)> = picture
+        .enumerate_pixels()
+        .filter(|&(x, y, pixel)| x % stride == 0 && y % stride == 0 && is_foreground(pixel))
+        .map(|(x, y, pixel)| {
+            let pos = offset + scale * (Vec2::new(x as f32, y as f32) + 0.5);
+            (pos, closest_species(pixel))
+        })
+        .collect();
+    boids.truncate(max_boids);
+    boids
+}
+
+/// Returns the red, green and blue of the given pixel (each between 0 and 1).
+fn rgb(pixel: &Pixel<u8>) -> Vec3 {
+    Vec3::new(pixel.0[0] as f32, pixel.0[1] as f32, pixel.0[2] as f32) / 255.
+}
+
+/// Returns the index of the species (in BOID_COLORS) whose color is the closest to the pixel's.
+fn closest_species(pixel: &Pixel<u8>) -> usize {
+    let distance = |species: usize| {
+        let color = BOID_COLORS[species];
+        Vec3::new(color.r, color.g, color.b).distance(rgb(pixel))
+    };
+    (0..BOID_COLORS.len())
+        .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(0)
+}
@@ -0,0 +1,110 @@
+use crate::constants::{
+    PREDATOR_FORCE, PREDATOR_SATED_SPEED, PREDATOR_SCALE, PREDATOR_SPEED, PREDATOR_WANDER_ANGLE,
+};
+use ggez::glam::Vec2;
+use ggez::graphics::{Color, DrawParam};
+use rand::rngs::StdRng;
+use rand::Rng;
+
+/// A predator hunting the boids. Predators chase the closest boid they can see, and boids flee from
+/// predators close to them. After catching a boid, a predator is sated for a while and doesn't hunt.
+#[derive(Clone)]
+pub struct Predator {
+    pos: Vec2,
+    speed: Vec2,
+    // Number of steps left until the predator hunts again:
+    satiation: u32,
+}
+
+impl Predator {
+    /// Creates a predator at the given position, heading in a random direction.
+    pub fn new(pos: Vec2, rng: &mut StdRng) -> Self {
+        Self {
+            pos,
+            speed: PREDATOR_SPEED * Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)),
+            satiation: 0,
+        }
+    }
+
+    /// Creates a predator at the given position, moving at the given speed.
+    pub fn with_speed(pos: Vec2, speed: Vec2) -> Self {
+        Self {
+            pos,
+            speed,
+            satiation: 0,
+        }
+    }
+
+    pub fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    pub fn speed(&self) -> Vec2 {
+        self.speed
+    }
+    pub fn satiation(&self) -> u32 {
+        self.satiation
+    }
+    pub fn is_sated(&self) -> bool {
+        self.satiation > 0
+    }
+    /// Sates the predator for the given number of steps. Sating an already sated predator starts
+    /// its satiation over rather than extending it.
+    pub fn sate(&mut self, steps: u32) {
+        self.satiation = steps;
+    }
+
+    /// Returns whether the predator can see the given point. Predators only see points in front of
+    /// them, up to half of the field of view (in degrees) away from their heading, and up to the
+    /// given range.
+    pub fn can_see(&self, point: Vec2, fov: f32, range: f32) -> bool {
+        let offset = point - self.pos;
+        if offset.length_squared() > range * range {
+            return false;
+        }
+        // A point exactly at the predator's position is always seen:
+        offset == Vec2::ZERO || self.speed.angle_between(offset).abs() <= fov.to_radians() / 2.
+    }
+
+    /// Steers the predator towards the given prey and moves it, wrapping around the borders of an
+    /// area of the given size. Without prey, the predator wanders around by turning slightly in a
+    /// random direction. Sated predators ignore the prey and wander around slowly, getting a step
+    /// closer to hunting again.
+    pub fn hunt(&mut self, prey: Option<Vec2>, rng: &mut StdRng, bounds: Vec2) {
+        let (prey, max_speed) = if self.is_sated() {
+            self.satiation -= 1;
+            (None, PREDATOR_SATED_SPEED)
+        } else {
+            (prey, PREDATOR_SPEED)
+        };
+        let desired = match prey {
+            Some(prey_pos) => PREDATOR_SPEED * (prey_pos - self.pos).normalize_or_zero(),
+            None => {
+                let turn = rng.gen_range(-PREDATOR_WANDER_ANGLE..=PREDATOR_WANDER_ANGLE);
+                Vec2::from_angle(turn.to_radians()).rotate(self.speed)
+            }
+        };
+
+        // Turn gradually, so boids can dodge a predator:
+        self.speed += (desired - self.speed).clamp_length_max(PREDATOR_FORCE);
+        self.speed = self.speed.clamp_length_max(max_speed);
+
+        self.pos += self.speed;
+        self.pos.x = self.pos.x.rem_euclid(bounds.x);
+        self.pos.y = self.pos.y.rem_euclid(bounds.y);
+    }
+
+    /// Returns the predator's drawing parameters for the boids' mesh. Predators are drawn larger
+    /// than boids, in red.
+    pub fn get_draw_param(&self) -> DrawParam {
+        DrawParam::new()
+            .dest(self.pos)
+            .rotation(self.rotation())
+            .scale(Vec2::splat(PREDATOR_SCALE))
+            .color(Color::RED)
+    }
+
+    /// Returns the angle (in radians, clockwise) the predator's shape is rotated by when drawn.
+    pub fn rotation(&self) -> f32 {
+        -self.speed.angle_between(Vec2::X)
+    }
+}
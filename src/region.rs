@@ -0,0 +1,91 @@
+use crate::camera::Camera;
+use crate::config::SimConfig;
+use crate::constants::{CAMERA_PADDING, MAX_CAMERA_ZOOM, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::simulation::Simulation;
+use ggez::glam::Vec2;
+use ggez::graphics::Rect;
+use std::collections::VecDeque;
+
+/// One of the independent simulations the window is split into, along with its camera and the
+/// part of the window it's drawn in.
+pub struct Region {
+    pub sim: Simulation,
+    pub camera: Camera,
+
+    // The part of the window the simulation is drawn in, in window coordinates. The simulation is
+    // as large as this part of the window, unless the configuration sets a world size:
+    pub bounds: Rect,
+
+    // Snapshots of the simulation taken while it runs, from the oldest to the newest:
+    pub history: VecDeque<Simulation>,
+}
+
+impl Region {
+    pub fn new(config: SimConfig, bounds: Rect) -> Self {
+        let screen_size = Vec2::new(bounds.w, bounds.h);
+        let size = config.world_size.map_or(screen_size, Vec2::from);
+        Self {
+            sim: Simulation::new(config, size),
+            camera: Camera::new(size, screen_size),
+            bounds,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Saves a snapshot of the simulation in its history, forgetting the oldest snapshots so at most
+    /// `length` snapshots are kept.
+    pub fn capture(&mut self, length: usize) {
+        self.history.push_back(self.sim.clone());
+        while self.history.len() > length {
+            self.history.pop_front();
+        }
+    }
+
+    /// Continues the simulation from the snapshot in the given index of its history. The snapshots
+    /// taken after it are forgotten, since the simulation may now go differently.
+    pub fn rewind_to(&mut self, index: usize) {
+        self.history.truncate(index + 1);
+        if let Some(snapshot) = self.history.back() {
+            self.sim = snapshot.clone();
+        }
+    }
+
+    /// Returns the screen coordinates that draw the camera's view inside the region's part of the
+    /// window.
+    pub fn screen_coordinates(&self) -> Rect {
+        let view = self.camera.view_rect();
+        let scale = view.w / self.bounds.w;
+        Rect::new(
+            view.x - self.bounds.x * scale,
+            view.y - self.bounds.y * scale,
+            SCREEN_WIDTH * scale,
+            SCREEN_HEIGHT * scale,
+        )
+    }
+
+    /// Converts a point in the window (e.g: the mouse's position) to simulation coordinates.
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        self.camera
+            .screen_to_world(point - Vec2::new(self.bounds.x, self.bounds.y))
+    }
+
+    /// Moves the camera over `dt` seconds. If the flock cam is on, the camera follows the center
+    /// of the flock and zooms to frame it, otherwise it goes back to showing the entire simulation.
+    pub fn update_camera(&mut self, flock_cam: bool, dt: f32) {
+        let full_zoom = self.camera.full_zoom();
+        let (mut center, mut zoom) = (self.sim.size() / 2., full_zoom);
+
+        if flock_cam && !self.sim.is_empty() {
+            center = self.sim.centroid();
+
+            // Zoom in as much as possible while keeping the entire flock in the view:
+            let bounding_box = self.sim.bounding_box();
+            let size = Vec2::new(bounding_box.w, bounding_box.h) + Vec2::splat(2. * CAMERA_PADDING);
+            zoom = (self.bounds.w / size.x)
+                .min(self.bounds.h / size.y)
+                .clamp(full_zoom, MAX_CAMERA_ZOOM.max(full_zoom));
+        }
+
+        self.camera.follow(center, zoom, dt);
+    }
+}
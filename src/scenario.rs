@@ -0,0 +1,220 @@
+use crate::boid::Boid;
+use crate::config::{ConfigError, SimConfig};
+use crate::constants::{BOID_COLORS, MAX_INFLUENCERS};
+use crate::obstacle::{CalmZone, Influencer, Obstacle, Reef};
+use crate::simulation::Simulation;
+use ggez::glam::Vec2;
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Circle {
+    pos: [f32; 2],
+    radius: f32,
+}
+
+/// A point attracting (positive strength) or repelling (negative strength) the boids.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Point {
+    pos: [f32; 2],
+    strength: f32,
+}
+
+/// Boids spawned around a position when the scenario starts, like right clicking does.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spawn {
+    pos: [f32; 2],
+    count: usize,
+    radius: f32,
+    species: usize,
+}
+
+/// A boid placed exactly, replacing the random initial boids.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlacedBoid {
+    pos: [f32; 2],
+    speed: [f32; 2],
+    species: usize,
+}
+
+/// A scenario describes the parameters and the initial state of the simulation, so a demo can be
+/// shared and started the same way every time. Positions are in simulation coordinates, i.e. the
+/// same in every region.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    // The parameters of the simulation, replacing the main configuration file:
+    #[serde(default)]
+    pub config: SimConfig,
+
+    // Number of initial boids of every species (by the config's proportions if not given):
+    species_counts: Option<Vec<usize>>,
+
+    // The exact initial boids, instead of random ones:
+    #[serde(default)]
+    boids: Vec<PlacedBoid>,
+
+    #[serde(default)]
+    obstacles: Vec<Circle>,
+    #[serde(default)]
+    calm_zones: Vec<Circle>,
+    #[serde(default)]
+    influencers: Vec<Point>,
+    #[serde(default)]
+    reefs: Vec<Circle>,
+    // Positions of predators:
+    #[serde(default)]
+    predators: Vec<[f32; 2]>,
+    #[serde(default)]
+    spawns: Vec<Spawn>,
+}
+
+impl Scenario {
+    /// Loads and validates the scenario in the given path.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let scenario: Self = toml::from_str(&text).map_err(ConfigError::Parse)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Checks that the scenario can be applied entirely, so a broken scenario is never applied only
+    /// partially.
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()?;
+
+        let species = BOID_COLORS.len();
+        if let Some(counts) = &self.species_counts {
+            if counts.len() != species {
+                return Err(ConfigError::Invalid(format!(
+                    "species_counts must have {species} counts"
+                )));
+            }
+            if counts.iter().sum::<usize>() > self.config.max_boids {
+                return Err(ConfigError::Invalid(
+                    "species_counts must not add up to more than max_boids".into(),
+                ));
+            }
+        }
+
+        if !self.boids.is_empty() {
+            if self.species_counts.is_some() {
+                return Err(ConfigError::Invalid(
+                    "boids replace the initial boids, so they can't be given with species_counts"
+                        .into(),
+                ));
+            }
+            if self.boids.len() > self.config.max_boids {
+                return Err(ConfigError::Invalid(
+                    "there must not be more boids than max_boids".into(),
+                ));
+            }
+        }
+
+        if self.influencers.len() > MAX_INFLUENCERS {
+            return Err(ConfigError::Invalid(format!(
+                "there must not be more than {MAX_INFLUENCERS} influencers"
+            )));
+        }
+        if self
+            .influencers
+            .iter()
+            .any(|influencer| !influencer.strength.is_finite())
+        {
+            return Err(ConfigError::Invalid(
+                "influencers' strengths must be finite".into(),
+            ));
+        }
+
+        // Collect every position and radius in the scenario (velocities are checked like
+        // positions):
+        let mut positions = self.predators.clone();
+        let mut radii = Vec::new();
+        for boid in self.boids.iter() {
+            positions.push(boid.pos);
+            positions.push(boid.speed);
+            if boid.species >= species {
+                return Err(ConfigError::Invalid(format!(
+                    "boids' species must be below {species}"
+                )));
+            }
+        }
+        let circles = self.obstacles.iter().chain(self.calm_zones.iter());
+        for circle in circles.chain(self.reefs.iter()) {
+            positions.push(circle.pos);
+            radii.push(circle.radius);
+        }
+        positions.extend(self.influencers.iter().map(|influencer| influencer.pos));
+        for spawn in self.spawns.iter() {
+            positions.push(spawn.pos);
+            radii.push(spawn.radius);
+            if spawn.species >= species {
+                return Err(ConfigError::Invalid(format!(
+                    "spawned species must be below {species}"
+                )));
+            }
+        }
+
+        if positions.iter().flatten().any(|coord| !coord.is_finite()) {
+            return Err(ConfigError::Invalid("positions must be finite".into()));
+        }
+        if radii.iter().any(|radius| radius.is_nan() || *radius < 0.) {
+            return Err(ConfigError::Invalid("radii must be non-negative".into()));
+        }
+        Ok(())
+    }
+
+    /// Sets up the initial state of the given simulation (which should use the scenario's
+    /// parameters) as the scenario describes.
+    pub fn apply(&self, sim: &mut Simulation) {
+        if let Some(counts) = &self.species_counts {
+            sim.respawn_boids(counts);
+        }
+        if !self.boids.is_empty() {
+            sim.set_boids(
+                self.boids
+                    .iter()
+                    .map(|boid| Boid::new(boid.pos[0], boid.pos[1], Vec2::ZERO, boid.species)),
+            );
+            for (idx, boid) in self.boids.iter().enumerate() {
+                sim.set_boid_velocity(idx, Vec2::from_array(boid.speed));
+            }
+        }
+        for obstacle in self.obstacles.iter() {
+            sim.add_obstacle(Obstacle::new(
+                Vec2::from_array(obstacle.pos),
+                obstacle.radius,
+            ));
+        }
+        for calm_zone in self.calm_zones.iter() {
+            sim.add_calm_zone(CalmZone::new(
+                Vec2::from_array(calm_zone.pos),
+                calm_zone.radius,
+            ));
+        }
+        for influencer in self.influencers.iter() {
+            sim.add_influencer(Influencer::new(
+                Vec2::from_array(influencer.pos),
+                influencer.strength,
+            ));
+        }
+        for reef in self.reefs.iter() {
+            sim.add_reef(Reef::new(Vec2::from_array(reef.pos), reef.radius));
+        }
+        for &pos in self.predators.iter() {
+            sim.add_predator(Vec2::from_array(pos));
+        }
+        for spawn in self.spawns.iter() {
+            sim.spawn_boids(
+                Vec2::from_array(spawn.pos),
+                spawn.count,
+                spawn.radius,
+                spawn.species,
+            );
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::boid::Boid;
+use crate::constants::BOID_COLORS;
+use crate::obstacle::{CalmZone, Influencer, Obstacle, Reef};
+use crate::predator::Predator;
+use crate::simulation::Simulation;
+use ggez::glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+// Vectors can't be serialized, so the saved state holds them as [x, y] arrays:
+
+#[derive(Serialize, Deserialize)]
+struct SavedBoid {
+    pos: [f32; 2],
+    speed: [f32; 2],
+    species: usize,
+    age: u32,
+}
+
+// Obstacles, calm zones and reefs are all saved as circles:
+#[derive(Serialize, Deserialize)]
+struct SavedCircle {
+    pos: [f32; 2],
+    radius: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedInfluencer {
+    pos: [f32; 2],
+    strength: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPredator {
+    pos: [f32; 2],
+    speed: [f32; 2],
+    #[serde(default)]
+    satiation: u32,
+}
+
+/// The state of a single simulation in a saved session. TOML needs a table's values before its
+/// arrays of tables, so empty arrays (which would come after the boids) aren't written at all.
+#[derive(Serialize, Deserialize)]
+struct SavedSimulation {
+    steps: u64,
+    restrict_walls: bool,
+    target: Option<[f32; 2]>,
+    leader_idx: Option<usize>,
+    #[serde(default)]
+    leader_repels: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    boids: Vec<SavedBoid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    obstacles: Vec<SavedCircle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    calm_zones: Vec<SavedCircle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    influencers: Vec<SavedInfluencer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reefs: Vec<SavedCircle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    predators: Vec<SavedPredator>,
+}
+
+/// The state of every region's simulation, saved so a run can continue after the window is closed.
+/// Only what changes while the simulation runs is saved: the parameters still come from the
+/// configuration files, and the randomness starts over from the seed.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    regions: Vec<SavedSimulation>,
+}
+
+impl Session {
+    /// Saves the state of the given simulations, in order.
+    pub fn capture<'a>(sims: impl IntoIterator<Item = &'a Simulation>) -> Self {
+        let regions = sims
+            .into_iter()
+            .map(|sim| SavedSimulation {
+                steps: sim.step_count(),
+                restrict_walls: sim.restrict_walls(),
+                target: sim.target().map(|target| target.to_array()),
+                leader_idx: sim.leader_idx(),
+                leader_repels: sim.leader_repels(),
+                boids: sim
+                    .boids()
+                    .map(|boid| SavedBoid {
+                        pos: boid.pos().to_array(),
+                        speed: boid.speed().to_array(),
+                        species: boid.species(),
+                        age: boid.age(),
+                    })
+                    .collect(),
+                obstacles: sim
+                    .obstacles()
+                    .iter()
+                    .map(|obstacle| SavedCircle {
+                        pos: obstacle.pos.to_array(),
+                        radius: obstacle.radius,
+                    })
+                    .collect(),
+                calm_zones: sim
+                    .calm_zones()
+                    .iter()
+                    .map(|calm_zone| SavedCircle {
+                        pos: calm_zone.pos.to_array(),
+                        radius: calm_zone.radius,
+                    })
+                    .collect(),
+                influencers: sim
+                    .influencers()
+                    .iter()
+                    .map(|influencer| SavedInfluencer {
+                        pos: influencer.pos.to_array(),
+                        strength: influencer.strength,
+                    })
+                    .collect(),
+                reefs: sim
+                    .reefs()
+                    .iter()
+                    .map(|reef| SavedCircle {
+                        pos: reef.pos.to_array(),
+                        radius: reef.radius,
+                    })
+                    .collect(),
+                predators: sim
+                    .predators()
+                    .iter()
+                    .map(|predator| SavedPredator {
+                        pos: predator.pos().to_array(),
+                        speed: predator.speed().to_array(),
+                        satiation: predator.satiation(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { regions }
+    }
+
+    /// Writes the session to the given path as a TOML file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, text)
+    }
+
+    /// Reads a session saved to the given path.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Restores the saved state into the given simulations, in order. If the number of simulations
+    /// changed since the session was saved, the extra simulations (or saved states) are left alone.
+    /// Saved boids of species that no longer exist are dropped (and so is the leader, if it was one
+    /// of them).
+    pub fn restore<'a>(&self, sims: impl IntoIterator<Item = &'a mut Simulation>) {
+        for (sim, saved) in sims.into_iter().zip(self.regions.iter()) {
+            let known = |boid: &&SavedBoid| boid.species < BOID_COLORS.len();
+            // The leader's index moves back past every dropped boid before it:
+            let leader_idx = saved
+                .leader_idx
+                .filter(|&idx| saved.boids.get(idx).is_some_and(|boid| known(&boid)))
+                .map(|idx| saved.boids[..idx].iter().filter(known).count());
+            sim.set_boids(saved.boids.iter().filter(known).map(|boid| {
+                let mut restored = Boid::new(
+                    boid.pos[0],
+                    boid.pos[1],
+                    Vec2::from_array(boid.speed),
+                    boid.species,
+                );
+                restored.set_age(boid.age);
+                restored
+            }));
+            sim.set_leader_idx(leader_idx);
+            sim.set_leader_repels(saved.leader_repels);
+            sim.set_step_count(saved.steps);
+            sim.set_restrict_walls(saved.restrict_walls);
+            sim.set_target(saved.target.map(Vec2::from_array));
+            for obstacle in saved.obstacles.iter() {
+                sim.add_obstacle(Obstacle::new(
+                    Vec2::from_array(obstacle.pos),
+                    obstacle.radius,
+                ));
+            }
+            for calm_zone in saved.calm_zones.iter() {
+                sim.add_calm_zone(CalmZone::new(
+                    Vec2::from_array(calm_zone.pos),
+                    calm_zone.radius,
+                ));
+            }
+            for influencer in saved.influencers.iter() {
+                sim.add_influencer(Influencer::new(
+                    Vec2::from_array(influencer.pos),
+                    influencer.strength,
+                ));
+            }
+            for reef in saved.reefs.iter() {
+                sim.add_reef(Reef::new(Vec2::from_array(reef.pos), reef.radius));
+            }
+            for saved_predator in saved.predators.iter() {
+                let mut predator = Predator::with_speed(
+                    Vec2::from_array(saved_predator.pos),
+                    Vec2::from_array(saved_predator.speed),
+                );
+                predator.sate(saved_predator.satiation);
+                sim.insert_predator(predator);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimConfig;
+    use crate::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    /// Returns a seeded simulation of the screen's size with the default parameters.
+    fn seeded_sim(seed: u64) -> Simulation {
+        let config = SimConfig {
+            seed: Some(seed),
+            ..SimConfig::default()
+        };
+        Simulation::new(config, Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT))
+    }
+
+    /// Returns the positions of the simulation's boids, in order.
+    fn positions(sim: &Simulation) -> Vec<Vec2> {
+        sim.boids().map(Boid::pos).collect()
+    }
+
+    #[test]
+    fn saved_session_restores_the_boids() {
+        let mut sim = seeded_sim(0);
+        for _ in 0..10 {
+            sim.step();
+        }
+        sim.add_obstacle(Obstacle::new(Vec2::new(200., 200.), 20.));
+        let path = std::env::temp_dir().join(format!("boids-session-{}.toml", std::process::id()));
+        Session::capture([&sim]).save(&path).unwrap();
+        let loaded = Session::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut restored = seeded_sim(1);
+        loaded.unwrap().restore([&mut restored]);
+        assert_eq!(restored.boids().count(), sim.boids().count());
+        assert_eq!(positions(&restored), positions(&sim));
+        assert_eq!(restored.step_count(), sim.step_count());
+        assert_eq!(restored.obstacles().len(), 1);
+    }
+
+    #[test]
+    fn restored_leader_skips_dropped_boids() {
+        let mut sim = seeded_sim(0);
+        sim.set_boids((0..3).map(|i| Boid::new(100. * (i + 1) as f32, 100., Vec2::X, 0)));
+        sim.set_leader_idx(Some(2));
+        let mut session = Session::capture([&sim]);
+        session.regions[0].boids[0].species = BOID_COLORS.len();
+
+        let mut restored = seeded_sim(0);
+        session.restore([&mut restored]);
+        assert_eq!(restored.leader_idx(), Some(1));
+        assert_eq!(positions(&restored)[1], Vec2::new(300., 100.));
+
+        // A leader of a dropped species leaves the restored simulation without a leader:
+        session.regions[0].boids[2].species = BOID_COLORS.len();
+        session.restore([&mut restored]);
+        assert_eq!(restored.leader_idx(), None);
+    }
+}
@@ -0,0 +1,3164 @@
+use crate::boid::{speed_limits, Boid, BoidView, GridBoid};
+use crate::color::Rgba;
+use crate::config::{
+    AlignmentMode, Edge, EdgeBehavior, EvictionPolicy, LeaderMode, SimConfig, SpacingModel,
+    SpawnHeading, WallPerception,
+};
+use crate::constants::*;
+use crate::grouping::DisjointSet;
+use crate::hull::convex_hull;
+use crate::obstacle::{Boundary, CalmZone, Influencer, Obstacle, Reef};
+use crate::predator::Predator;
+use crate::stats::{CellInspection, GroupStats, RuleTrace, RULE_COUNT};
+use ggez::glam::Vec2;
+use ggez::graphics::Rect;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU64;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+/// A function scripting a simulation (see `Simulation::set_tick_hook`). Clones of the simulation
+/// (e.g: snapshots in the timeline) share the same function.
+type TickHook = Arc<Mutex<dyn FnMut(&mut Simulation) + Send>>;
+
+/// Runs the given function for all cells in the grid up to `rings` cells away from the current cell
+/// AND THE CURRENT CELL (e.g: one ring is the adjacent cells).
+fn run_for_neighbor_cells<F>(
+    row: usize,
+    col: usize,
+    width: usize,
+    height: usize,
+    rings: usize,
+    mut f: F,
+) where
+    F: FnMut(usize, usize),
+{
+    let rings = rings as isize;
+    for row_shift in -rings..=rings {
+        // Validate row:
+        let current_row = row_shift + row as isize;
+        if current_row >= 0 && current_row < height as isize {
+            for col_shift in -rings..=rings {
+                // Validate column:
+                let current_col = col_shift + col as isize;
+                if current_col >= 0 && current_col < width as isize {
+                    // Fun method:
+                    f(current_row as usize, current_col as usize);
+                }
+            }
+        }
+    }
+}
+
+/// Removes the given boid index from a cell of the location grid, keeping the order of the other
+/// boids in the cell.
+fn remove_from_cell(cell: &mut Vec<usize>, idx: usize) {
+    if let Some(position) = cell.iter().position(|&entry| entry == idx) {
+        cell.remove(position);
+    }
+}
+
+/// Returns the polygon the boids are confined to with the given parameters (if any).
+fn boundary_of(config: &SimConfig) -> Option<Boundary> {
+    let points = config.boundary_polygon.as_ref()?;
+    Some(Boundary::new(
+        points.iter().copied().map(Vec2::from_array).collect(),
+    ))
+}
+
+/// Returns the number of threads the rules are split between with the given parameters.
+fn worker_threads(config: &SimConfig) -> usize {
+    config
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |threads| threads.get()))
+        .max(1)
+}
+
+/// Returns the number of columns and rows of the location grid of a simulation of the given size.
+/// There's a cell every `INFLUENCE_DISTANCE`, plus one for the points on the far edges (and past
+/// the last whole cell).
+fn grid_size(size: Vec2) -> (usize, usize) {
+    (
+        (size.x / INFLUENCE_DISTANCE) as usize + 1,
+        (size.y / INFLUENCE_DISTANCE) as usize + 1,
+    )
+}
+
+/// Returns the index of the cell of the location grid (on an axis with the given number of cells)
+/// the given coordinate is in. A coordinate exactly on the boundary between two cells is in the
+/// latter, and coordinates outside the grid (or NaN) are clamped into it, so every coordinate maps
+/// to exactly one cell. Every mapping of points to cells goes through this, so boids are always
+/// found in the cell they were put in.
+fn cell_index(coord: f32, count: usize) -> usize {
+    ((coord / INFLUENCE_DISTANCE).floor().max(0.) as usize).min(count - 1)
+}
+
+/// Returns the row and column of the cell of the location grid (with the given number of columns
+/// and rows) the given point is in (see `cell_index`).
+fn cell_of(pos: Vec2, grid_width: usize, grid_height: usize) -> (usize, usize) {
+    (
+        cell_index(pos.y, grid_height),
+        cell_index(pos.x, grid_width),
+    )
+}
+
+/// Returns the range of cells of the location grid (on an axis with the given number of cells) that
+/// the range between the given coordinates overlaps. Coordinates outside the grid are clamped to it.
+fn cell_span(from: f32, to: f32, count: usize) -> RangeInclusive<usize> {
+    cell_index(from, count)..=cell_index(to, count)
+}
+
+/// Returns a random initial speed for a boid spawned at the given offset from the center it was
+/// spawned around, going in the direction the spawn heading gives with a random magnitude between
+/// the minimum and maximum boid velocities.
+fn random_speed(rng: &mut StdRng, heading: SpawnHeading, offset: Vec2) -> Vec2 {
+    let heading = heading.direction(offset, rng);
+    rng.gen_range(speed_limits(1.)) * heading
+}
+
+/// Splits `total` boids between the species according to their shares. Every species gets its share
+/// rounded down, and the boids left after rounding go to the species with the largest remainders,
+/// so the counts always add up to `total`.
+fn species_counts(proportions: &[f32], total: usize) -> Vec<usize> {
+    let sum: f32 = proportions.iter().sum();
+    let exact: Vec<f32> = proportions
+        .iter()
+        .map(|share| share / sum * total as f32)
+        .collect();
+    let mut counts: Vec<usize> = exact.iter().map(|count| *count as usize).collect();
+
+    // Give the boids lost to rounding to the largest remainders (the first species win ties):
+    let mut by_remainder: Vec<usize> = (0..counts.len()).collect();
+    by_remainder
+        .sort_by(|&a, &b| (exact[b] - counts[b] as f32).total_cmp(&(exact[a] - counts[a] as f32)));
+    let left = total.saturating_sub(counts.iter().sum());
+    for &species in by_remainder.iter().cycle().take(left) {
+        counts[species] += 1;
+    }
+    counts
+}
+
+/// The boids simulation itself, without any windowing or rendering. The simulation advances one
+/// step at a time by calling `step`. Cloning a simulation gives a snapshot of its entire state
+/// (including its randomness), so a cloned simulation continues exactly like the original.
+#[derive(Clone)]
+pub struct Simulation {
+    // The size of the area the boids live in (starting at (0, 0)), and the distance from its
+    // borders at which boids start evading them:
+    size: Vec2,
+    margin: f32,
+
+    // The polygon the boids are confined to (from the config):
+    boundary: Option<Boundary>,
+
+    // The grid divides the area into cells, and each cell contains a list of the boids in it. The
+    // grid only saves indices to the 'boids' vector (to avoid references). Cells are vectors rather
+    // than sets, so boids are always visited in the same order and seeded runs are reproducible:
+    location_grid: Vec<Vec<Vec<usize>>>,
+    grid_width: usize,
+    grid_height: usize,
+
+    // The trail left by the boids in every cell of the location grid (row by row):
+    trail: Vec<f32>,
+
+    // All boids in the simulation and their indices in the location grid, and the identifier the
+    // next boid added gets:
+    boids: Vec<GridBoid>,
+    next_id: u64,
+
+    // A location all boids will aim towards:
+    target: Option<Vec2>,
+
+    // Whether boids should avoid walls or not:
+    restrict_walls: bool,
+
+    // Static obstacles the boids avoid:
+    obstacles: Vec<Obstacle>,
+
+    // Areas where boids barely steer:
+    calm_zones: Vec<CalmZone>,
+
+    // Points attracting or repelling the boids (up to MAX_INFLUENCERS):
+    influencers: Vec<Influencer>,
+
+    // Areas the boids loiter in:
+    reefs: Vec<Reef>,
+
+    // Predators hunting the boids:
+    predators: Vec<Predator>,
+
+    // Index of the leader boid, the point boids follow (which moves to a new leader gradually, and
+    // stays at the last leader's position when the leader is removed), and the number of steps left
+    // until it reaches the leader:
+    leader_idx: Option<usize>,
+    leader_pos: Option<Vec2>,
+    handoff_steps_left: u32,
+
+    // Multiplies the leader rule's pull (1 for boids following the leaders, -1 for boids fleeing
+    // from them):
+    leader_sign: f32,
+
+    // With a leader per group, the index of the leader of every boid's group (none for groups too
+    // small to have a leader), indexed like the boids. Empty unless groups have leaders, and
+    // recalculated every step:
+    group_leaders: Vec<Option<usize>>,
+
+    // Indices of the boids tethered to a point by a spring (e.g: grabbed with the mouse), and the
+    // point (only set while boids are tethered):
+    tethered: Vec<usize>,
+    tether_anchor: Option<Vec2>,
+
+    // The center of the time lens slowing down the boids around it, if there is one:
+    time_lens: Option<Vec2>,
+
+    // Index of the boid sprinting (e.g: while the user holds a key over it):
+    sprinter: Option<usize>,
+
+    // Indices of the boids the user selected (e.g: with a selection box), which group editing
+    // applies to:
+    selection: HashSet<usize>,
+
+    // Index of the boid whose rules are traced (one of the selected boids), and what steered it in
+    // the last step (only set after a step with the boid traced):
+    traced: Option<usize>,
+    trace: Option<RuleTrace>,
+
+    // The direction each boid is pushed in while the flock is split in two (empty when it isn't),
+    // and the number of steps left until the two halves are released:
+    split_pushes: Vec<Vec2>,
+    split_steps_left: u32,
+
+    // Parameters of the simulation, and the number of threads the rules are split between:
+    config: SimConfig,
+    threads: usize,
+
+    // Source of all randomness in the simulation, seeded from the config (if a seed is given):
+    rng: StdRng,
+
+    // For every pair of differently colored groups touching each other, the number of steps they've
+    // been touching for. Groups are identified by their representative boid:
+    group_contacts: HashMap<(usize, usize), u32>,
+
+    // Whether the neighbors found using the location grid matched the ones found by checking every
+    // pair of boids in the last step (only checked if `verify_neighbors` is set):
+    neighbors_match: Option<bool>,
+
+    // The average position of all boids and the smallest rectangle containing them, updated after
+    // every step:
+    centroid: Vec2,
+    bounding_box: Rect,
+
+    // Number of steps the simulation ran, and the simulated time they took (in seconds):
+    steps: u64,
+    time: f64,
+
+    // The part of a boid waiting to flow in, since the inflow's rate isn't necessarily whole:
+    inflow_pending: f32,
+
+    // A function scripting the simulation, and the number of steps between its runs:
+    tick_hook: Option<(NonZeroU64, TickHook)>,
+
+    // The external excitement level, from 0 to 1 (see EXCITEMENT_SPEED):
+    excitement: f32,
+
+    // The index of every boid by its identifier, updated every step while boids remember their
+    // neighbors (empty otherwise):
+    id_indices: HashMap<u64, usize>,
+
+    // The directions every rule last gave every boid, which are reused on the steps the rule isn't
+    // recalculated on (see RULE_INTERVALS):
+    rule_cache: Vec<Vec<Vec2>>,
+    // The directions every rule (including the noise) gave every boid in the last step, as they
+    // were combined (empty until the first step after the boids were replaced):
+    rule_contributions: Vec<Vec<Vec2>>,
+}
+
+impl Simulation {
+    /// Creates a simulation of boids living in an area of the given size. Both the margin and the
+    /// number of boids are scaled relative to the screen, so a smaller area is just as crowded (and a
+    /// larger area is just as crowded up to the maximum number of boids, with the screen's margin).
+    pub fn new(config: SimConfig, size: Vec2) -> Self {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let margin = MARGIN * (size.x / SCREEN_WIDTH).min(1.);
+        let (location_grid, boids) = Self::get_random_boids(&mut rng, size, margin, &config);
+
+        let mut sim = Self {
+            size,
+            margin,
+            boundary: boundary_of(&config),
+            grid_width: location_grid[0].len(),
+            grid_height: location_grid.len(),
+            trail: vec![0.; location_grid.len() * location_grid[0].len()],
+            location_grid,
+            next_id: boids.len() as u64,
+            boids,
+            target: None,
+            restrict_walls: true,
+            obstacles: Vec::new(),
+            calm_zones: Vec::new(),
+            influencers: Vec::new(),
+            reefs: Vec::new(),
+            predators: Vec::new(),
+            leader_idx: None,
+            leader_pos: None,
+            handoff_steps_left: 0,
+            leader_sign: 1.,
+            group_leaders: Vec::new(),
+            tethered: Vec::new(),
+            selection: HashSet::new(),
+            sprinter: None,
+            traced: None,
+            trace: None,
+            tether_anchor: None,
+            time_lens: None,
+            split_pushes: Vec::new(),
+            split_steps_left: 0,
+            threads: worker_threads(&config),
+            config,
+            rng,
+            group_contacts: HashMap::new(),
+            neighbors_match: None,
+            centroid: Vec2::ZERO,
+            bounding_box: Rect::default(),
+            steps: 0,
+            time: 0.,
+            inflow_pending: 0.,
+            tick_hook: None,
+            excitement: 0.,
+            id_indices: HashMap::new(),
+            rule_cache: vec![Vec::new(); RULE_COUNT - 1],
+            rule_contributions: Vec::new(),
+        };
+        sim.update_flock_bounds();
+        sim
+    }
+
+    /// Returns the size of the area the boids live in.
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    /// Returns an iterator over all boids in the simulation.
+    pub fn boids(&self) -> impl Iterator<Item = &Boid> {
+        self.boids.iter().map(|grid_boid| &grid_boid.boid)
+    }
+
+    /// Returns views of all boids, which stay the same however the simulation stores its boids.
+    pub fn iter_boids(&self) -> impl Iterator<Item = BoidView> + '_ {
+        self.boids.iter().map(BoidView::new)
+    }
+
+    /// Replaces all boids in the simulation with the given boids, wrapping them into its area. The
+    /// simulation is left without a leader, since the leader's index belonged to the old boids.
+    pub fn set_boids(&mut self, boids: impl IntoIterator<Item = Boid>) {
+        for cell in self.location_grid.iter_mut().flatten() {
+            cell.clear();
+        }
+        self.boids.clear();
+        self.rule_cache.iter_mut().for_each(Vec::clear);
+        self.rule_contributions.clear();
+        for boid in boids {
+            self.push_boid(boid);
+        }
+
+        self.leader_idx = None;
+        self.leader_pos = None;
+        self.release_tether();
+        self.selection.clear();
+        self.sprinter = None;
+        self.stop_trace();
+        self.split_pushes.clear();
+        self.group_contacts.clear();
+        self.group_leaders.clear();
+        self.update_flock_bounds();
+    }
+
+    /// Replaces all boids in the simulation with new boids at random positions (away from the
+    /// walls), with the given number of boids of every species. The boids are spawned around the
+    /// center of the area.
+    pub fn respawn_boids(&mut self, counts: &[usize]) {
+        let (size, margin, heading) = (self.size, self.margin, self.config.spawn_heading);
+        let boids: Vec<Boid> = counts
+            .iter()
+            .enumerate()
+            .flat_map(|(species, &count)| std::iter::repeat_n(species, count))
+            .map(|species| {
+                let x = self.rng.gen_range(margin..size.x - margin);
+                let y = self.rng.gen_range(margin..size.y - margin);
+                let speed = random_speed(&mut self.rng, heading, Vec2::new(x, y) - size / 2.);
+                Boid::new(x, y, speed, species)
+            })
+            .collect();
+        self.set_boids(boids);
+    }
+
+    /// Replaces all boids in the simulation with boids of the given species in the given positions,
+    /// going in the directions the spawn heading gives (like the initial boids).
+    pub fn place_boids(&mut self, boids: &[(Vec2, usize)]) {
+        let (center, heading) = (self.size / 2., self.config.spawn_heading);
+        let boids: Vec<Boid> = boids
+            .iter()
+            .map(|&(pos, species)| {
+                let speed = random_speed(&mut self.rng, heading, pos - center);
+                Boid::new(pos.x, pos.y, speed, species)
+            })
+            .collect();
+        self.set_boids(boids);
+    }
+
+    /// Sets the velocity of the boid in the given index directly (within the boids' velocity
+    /// limits).
+    pub fn set_boid_velocity(&mut self, idx: usize, speed: Vec2) {
+        self.boids[idx].boid.set_speed(speed);
+    }
+
+    /// Returns whether there are no boids in the simulation.
+    pub fn is_empty(&self) -> bool {
+        self.boids.is_empty()
+    }
+
+    /// Spawns `count` boids of the given species at random positions up to `radius` away from the
+    /// given position. If this puts the simulation over its maximum number of boids, boids that were
+    /// already there are evicted.
+    pub fn spawn_boids(&mut self, pos: Vec2, count: usize, radius: f32, species: usize) {
+        let spawned = count.min(self.config.max_boids);
+        for _ in 0..spawned {
+            let offset = radius
+                * self.rng.gen_range(0f32..1.).sqrt()
+                * Vec2::from_angle(self.rng.gen_range(0.0..std::f32::consts::TAU));
+            let speed = random_speed(&mut self.rng, self.config.spawn_heading, offset);
+            let mut boid = Boid::new(pos.x, pos.y, speed, species);
+            boid.move_by(offset, self.size);
+            self.push_boid(boid);
+        }
+
+        // Evict boids (but not the ones that were just spawned) to get back under the maximum:
+        let excess = self.boids.len().saturating_sub(self.config.max_boids);
+        let candidates = self.boids.len() - spawned;
+        let mut evicted: Vec<usize> = match self.config.eviction_policy {
+            EvictionPolicy::Oldest => {
+                let mut by_age: Vec<usize> = (0..candidates).collect();
+                by_age.sort_by_key(|&i| std::cmp::Reverse(self.boids[i].boid.age()));
+                by_age.truncate(excess);
+                by_age
+            }
+            EvictionPolicy::Random => {
+                rand::seq::index::sample(&mut self.rng, candidates, excess).into_vec()
+            }
+        };
+
+        // Removing a boid moves the last boid to its index, so boids are removed from the last one
+        // to keep the other indices valid:
+        evicted.sort_unstable_by(|a, b| b.cmp(a));
+        for idx in evicted {
+            self.remove_boid(idx);
+        }
+        self.update_flock_bounds();
+    }
+
+    /// Adds the given boid to the simulation (and to the location grid), wrapping it into the
+    /// simulation's area.
+    fn push_boid(&mut self, mut boid: Boid) {
+        boid.move_by(Vec2::ZERO, self.size);
+        let (row, col) = cell_of(boid.pos(), self.grid_width, self.grid_height);
+        self.location_grid[row][col].push(self.boids.len());
+        // The new boid wasn't steered by any rule yet:
+        for directions in self
+            .rule_cache
+            .iter_mut()
+            .chain(self.rule_contributions.iter_mut())
+        {
+            if directions.len() == self.boids.len() {
+                directions.push(Vec2::ZERO);
+            }
+        }
+        self.boids.push(GridBoid {
+            boid,
+            row,
+            col,
+            id: self.next_id,
+            memory: Vec::new(),
+            panic: 0.,
+            panic_dir: Vec2::ZERO,
+        });
+        self.next_id += 1;
+
+        // Boids added while the flock is split aren't part of either half:
+        if !self.split_pushes.is_empty() {
+            self.split_pushes.push(Vec2::ZERO);
+        }
+    }
+
+    /// Lets boids flow in through the inflow's edge, if there is one. Every step adds the inflow's
+    /// rate to the boids waiting to enter, and whole boids enter (as long as the simulation isn't
+    /// at its maximum number of boids). Species are picked at random by their proportions.
+    fn flow_in(&mut self) {
+        let Some(inflow) = self.config.inflow else {
+            return;
+        };
+        self.inflow_pending += inflow.rate;
+
+        let species = WeightedIndex::new(&self.config.species_proportions)
+            .expect("species proportions are validated");
+        while self.inflow_pending >= 1. {
+            self.inflow_pending -= 1.;
+            if self.boids.len() >= self.config.max_boids {
+                continue;
+            }
+
+            // Enter at a random position along the edge, going straight in:
+            let (x, y) = (
+                self.rng.gen_range(0. ..self.size.x),
+                self.rng.gen_range(0. ..self.size.y),
+            );
+            let (pos, heading) = match inflow.edge {
+                Edge::Top => (Vec2::new(x, 0.), Vec2::Y),
+                Edge::Right => (Vec2::new(self.size.x.next_down(), y), Vec2::NEG_X),
+                Edge::Bottom => (Vec2::new(x, self.size.y.next_down()), Vec2::NEG_Y),
+                Edge::Left => (Vec2::new(0., y), Vec2::X),
+            };
+            let boid = Boid::new(
+                pos.x,
+                pos.y,
+                inflow.speed * heading,
+                species.sample(&mut self.rng),
+            );
+            self.push_boid(boid);
+        }
+    }
+
+    /// Removes the boids up to `radius` away from the given point, and returns how many were
+    /// removed.
+    pub fn remove_boids_near(&mut self, point: Vec2, radius: f32) -> usize {
+        let mut removed = self.boids_in_radius(point, radius);
+
+        // Removing a boid moves the last boid to its index, so boids are removed from the last one
+        // to keep the other indices valid:
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        for &idx in removed.iter() {
+            self.remove_boid(idx);
+        }
+        self.update_flock_bounds();
+        removed.len()
+    }
+
+    /// Removes the boid in the given index. The last boid takes its place, and the location grid
+    /// and the leader's index are updated accordingly. Every removal of boids (e.g: eviction, open
+    /// edges) goes through here, so the indices stay consistent.
+    /// The centroid and bounding box of the flock are only updated at the end of the next step.
+    /// Panics if there's no boid in the given index (which is always the case with no boids).
+    pub fn remove_boid(&mut self, idx: usize) {
+        let last = self.boids.len() - 1;
+        let removed = self.boids.swap_remove(idx);
+        remove_from_cell(&mut self.location_grid[removed.row][removed.col], idx);
+        for directions in self
+            .rule_cache
+            .iter_mut()
+            .chain(self.rule_contributions.iter_mut())
+        {
+            if directions.len() == last + 1 {
+                directions.swap_remove(idx);
+            }
+        }
+
+        // Update the index of the boid that was moved:
+        if idx != last {
+            let moved = &self.boids[idx];
+            let cell = &mut self.location_grid[moved.row][moved.col];
+            if let Some(entry) = cell.iter_mut().find(|entry| **entry == last) {
+                *entry = idx;
+            }
+        }
+
+        // Update the leader:
+        if self.leader_idx == Some(idx) {
+            self.leader_idx = None;
+        } else if self.leader_idx == Some(last) {
+            self.leader_idx = Some(idx);
+        }
+
+        // Update the tether:
+        self.tethered.retain(|&tethered| tethered != idx);
+        for tethered in self
+            .tethered
+            .iter_mut()
+            .filter(|tethered| **tethered == last)
+        {
+            *tethered = idx;
+        }
+        if self.tethered.is_empty() {
+            self.tether_anchor = None;
+        }
+
+        // Update the sprinter:
+        if self.sprinter == Some(idx) {
+            self.sprinter = None;
+        } else if self.sprinter == Some(last) {
+            self.sprinter = Some(idx);
+        }
+
+        // Update the traced boid:
+        if self.traced == Some(idx) {
+            self.stop_trace();
+        } else if self.traced == Some(last) {
+            self.traced = Some(idx);
+        }
+
+        // Update the selection:
+        self.selection.remove(&idx);
+        if self.selection.remove(&last) {
+            self.selection.insert(idx);
+        }
+
+        // Update the split (which is indexed like the boids):
+        if !self.split_pushes.is_empty() {
+            self.split_pushes.swap_remove(idx);
+        }
+
+        // Update the contact times of the groups, which are identified by their boid with the
+        // smallest index. A group whose representative was removed starts touching over:
+        let moved = |group| if group == last { idx } else { group };
+        self.group_contacts = std::mem::take(&mut self.group_contacts)
+            .into_iter()
+            .filter(|&((a, b), _)| a != idx && b != idx)
+            .map(|((a, b), steps)| {
+                let (a, b) = (moved(a), moved(b));
+                ((a.min(b), a.max(b)), steps)
+            })
+            .collect();
+
+        // The group leaders are picked again in the next step:
+        self.group_leaders.clear();
+    }
+
+    /// Returns the indices of the boids up to `radius` away from the given point (which doesn't have
+    /// to be inside the simulation). Only the cells of the location grid the circle overlaps are
+    /// checked, however large the radius is.
+    pub fn boids_in_radius(&self, center: Vec2, radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        for row in cell_span(center.y - radius, center.y + radius, self.grid_height) {
+            for col in cell_span(center.x - radius, center.x + radius, self.grid_width) {
+                found.extend(self.location_grid[row][col].iter().filter(|&&idx| {
+                    self.boids[idx].boid.pos().distance_squared(center) <= radius * radius
+                }));
+            }
+        }
+        found
+    }
+
+    /// Returns the index of the closest boid up to `max_radius` away from the given point, if there
+    /// is one. Boids exactly as close as each other are told apart by which was added first, so the
+    /// same boid is chosen however the boids are ordered (e.g: in a seeded replay).
+    pub fn nearest_boid(&self, point: Vec2, max_radius: f32) -> Option<usize> {
+        self.boids_in_radius(point, max_radius)
+            .into_iter()
+            .min_by(|&a, &b| {
+                let distance = |idx: usize| self.boids[idx].boid.pos().distance_squared(point);
+                distance(a)
+                    .total_cmp(&distance(b))
+                    .then(self.boids[a].id.cmp(&self.boids[b].id))
+            })
+    }
+
+    /// Returns the number of boids up to `INFLUENCE_DISTANCE` away from every boid (of any species),
+    /// in the order of `boids`.
+    pub fn neighbor_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.boids.len()];
+        self.for_each_close_pair(INFLUENCE_DISTANCE_SQUARED, false, |i, j| {
+            counts[i] += 1;
+            counts[j] += 1;
+        });
+        counts
+    }
+
+    /// Returns how much every boid influences the alignment and cohesion of the boids close to it,
+    /// in the order of `boids`: the sum of the influence it has on every boid up to
+    /// `INFLUENCE_DISTANCE` away (so a boid influences more the more boids care about its species).
+    pub fn influence_received(&self) -> Vec<f32> {
+        let mut received = vec![0.; self.boids.len()];
+        self.for_each_close_pair(INFLUENCE_DISTANCE_SQUARED, false, |i, j| {
+            let (this, other) = (&self.boids[i].boid, &self.boids[j].boid);
+            received[j] += self.influence(this, other).abs();
+            received[i] += self.influence(other, this).abs();
+        });
+        received
+    }
+
+    /// Returns the indices of the boids inside the given rectangle (which doesn't have to be inside
+    /// the simulation). Only the cells of the location grid the rectangle overlaps are checked.
+    fn boids_in_rect(&self, area: Rect) -> Vec<usize> {
+        let mut found = Vec::new();
+        for row in cell_span(area.top(), area.bottom(), self.grid_height) {
+            for col in cell_span(area.left(), area.right(), self.grid_width) {
+                found.extend(
+                    self.location_grid[row][col]
+                        .iter()
+                        .filter(|&&idx| area.contains(self.boids[idx].boid.pos())),
+                );
+            }
+        }
+        found
+    }
+
+    /// Returns pairs of a boid and a close boid influencing its alignment and cohesion (i.e: a boid
+    /// up to `INFLUENCE_DISTANCE` away whose species the first boid's species cares about), with up
+    /// to `max_links` pairs for every boid.
+    pub fn influence_links(&self, max_links: usize) -> Vec<(&Boid, &Boid)> {
+        let mut links = Vec::new();
+        for (i, this) in self.boids.iter().enumerate() {
+            let mut count = 0;
+            run_for_neighbor_cells(
+                this.row,
+                this.col,
+                self.grid_width,
+                self.grid_height,
+                self.config.neighbor_rings,
+                |row, col| {
+                    for &other_idx in self.cell_boids(row, col) {
+                        let other = &self.boids[other_idx];
+                        if count == max_links
+                            || i == other_idx
+                            || this.boid.pos().distance_squared(other.boid.pos())
+                                > INFLUENCE_DISTANCE_SQUARED
+                            || self
+                                .config
+                                .relationship(this.boid.species(), other.boid.species())
+                                == 0.
+                        {
+                            continue;
+                        }
+                        links.push((&this.boid, &other.boid));
+                        count += 1;
+                    }
+                },
+            );
+        }
+        links
+    }
+
+    /// Pushes the boids close to the segment between the given points along it, like a gust of wind
+    /// blowing from the first point to the second. The push is proportional to the segment's length
+    /// (i.e: how fast the gust is), and gets weaker the farther boids are from the segment.
+    pub fn apply_gust(&mut self, from: Vec2, to: Vec2) {
+        let (radius, strength) = (self.config.gust_radius, self.config.gust_strength);
+        let swipe = to - from;
+
+        // Only boids in the circle around the segment can be close enough to it:
+        let candidates = self.boids_in_radius(from + swipe / 2., swipe.length() / 2. + radius);
+        for idx in candidates {
+            // Find the distance from the boid to the closest point on the segment:
+            let grid_boid = &mut self.boids[idx];
+            let pos = grid_boid.boid.pos();
+            let t = if swipe == Vec2::ZERO {
+                0.
+            } else {
+                ((pos - from).dot(swipe) / swipe.length_squared()).clamp(0., 1.)
+            };
+            let distance = pos.distance(from + t * swipe);
+
+            if distance < radius {
+                grid_boid
+                    .boid
+                    .add_dir(strength * (1. - distance / radius) * swipe);
+                grid_boid.panic = 1.;
+                grid_boid.panic_dir = swipe.normalize_or_zero();
+            }
+        }
+    }
+
+    /// Returns the leader boid, if there is one.
+    pub fn leader(&self) -> Option<&Boid> {
+        self.leader_idx.map(|idx| &self.boids[idx].boid)
+    }
+    pub fn leader_idx(&self) -> Option<usize> {
+        self.leader_idx
+    }
+    /// Returns whether boids flee from the leaders instead of following them.
+    pub fn leader_repels(&self) -> bool {
+        self.leader_sign < 0.
+    }
+    /// Sets whether boids flee from the leaders (pushed away as hard as they'd be pulled) instead
+    /// of following them, which herds the flock away from wherever the leaders go.
+    pub fn set_leader_repels(&mut self, repels: bool) {
+        self.leader_sign = if repels { -1. } else { 1. };
+    }
+    /// Sets the leader's index. Indices of boids that don't exist (e.g: when there are no boids) are
+    /// ignored and leave the simulation without a leader. If there was a leader before, the point
+    /// boids follow moves to the new leader over `leader_handoff_steps` steps.
+    pub fn set_leader_idx(&mut self, leader_idx: Option<usize>) {
+        let leader_idx = leader_idx.filter(|&idx| idx < self.boids.len());
+        if self.leader_pos.is_some() && leader_idx.is_some() && leader_idx != self.leader_idx {
+            self.handoff_steps_left = self.config.leader_handoff_steps;
+        }
+        self.leader_idx = leader_idx;
+        if self.leader_pos.is_none() {
+            self.update_leader_pos();
+        }
+    }
+
+    /// Tethers the boids up to `radius` away from the given point to it, instead of the boids that
+    /// were tethered before. Returns whether any boids were tethered.
+    pub fn grab_boids(&mut self, point: Vec2, radius: f32) -> bool {
+        self.tethered = self.boids_in_radius(point, radius);
+        self.tether_anchor = (!self.tethered.is_empty()).then_some(point);
+        self.tether_anchor.is_some()
+    }
+    /// Moves the point the boids are tethered to (if any boids are tethered).
+    pub fn move_tether(&mut self, anchor: Vec2) {
+        if self.tether_anchor.is_some() {
+            self.tether_anchor = Some(anchor);
+        }
+    }
+    pub fn release_tether(&mut self) {
+        self.tethered.clear();
+        self.tether_anchor = None;
+    }
+    /// Returns the center of the time lens, if there is one.
+    pub fn time_lens(&self) -> Option<Vec2> {
+        self.time_lens
+    }
+    /// Sets the center of the time lens (or removes it). Boids up to `time_lens_radius` away from
+    /// the center move and steer `time_lens_factor` of their usual amount every step.
+    pub fn set_time_lens(&mut self, center: Option<Vec2>) {
+        self.time_lens = center;
+    }
+    /// Returns how fast time passes for every boid, from 1 outside the time lens to
+    /// `time_lens_factor` inside it.
+    fn time_scales(&self) -> Vec<f32> {
+        let Some(center) = self.time_lens else {
+            return vec![1.; self.boids.len()];
+        };
+        let radius = self.config.time_lens_radius;
+        self.boids
+            .iter()
+            .map(|grid_boid| {
+                if grid_boid.boid.pos().distance_squared(center) < radius * radius {
+                    self.config.time_lens_factor
+                } else {
+                    1.
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the point boids are tethered to, if any boids are tethered.
+    pub fn tether_anchor(&self) -> Option<Vec2> {
+        self.tether_anchor
+    }
+    /// Returns an iterator over the tethered boids.
+    pub fn tethered_boids(&self) -> impl Iterator<Item = &Boid> {
+        self.tethered.iter().map(|&idx| &self.boids[idx].boid)
+    }
+
+    /// Makes the closest boid up to `radius` away from the given point sprint, instead of the boid
+    /// that sprinted before. A sprinting boid can go `SPRINT_MULTIPLIER` times faster than usual,
+    /// and is pushed forward by `SPRINT_THRUST`. Returns whether a boid sprints.
+    pub fn start_sprint(&mut self, point: Vec2, radius: f32) -> bool {
+        self.stop_sprint();
+        self.sprinter = self.nearest_boid(point, radius);
+        self.update_speed_scales();
+        self.sprinter.is_some()
+    }
+    /// Stops the sprinting boid (if any), bringing it back to its usual maximum velocity.
+    pub fn stop_sprint(&mut self) {
+        if self.sprinter.take().is_some() {
+            self.update_speed_scales();
+        }
+    }
+
+    /// Sets the excitement level, which is kept between 0 and 1. Excited boids fly faster and keep
+    /// farther apart (see EXCITEMENT_SPEED), starting from the next step.
+    pub fn set_excitement(&mut self, level: f32) {
+        self.excitement = if level.is_nan() {
+            0.
+        } else {
+            level.clamp(0., 1.)
+        };
+    }
+    /// Returns how much the excitement scales a parameter with the given gain.
+    fn excitement_scale(&self, gain: f32) -> f32 {
+        1. + gain * self.excitement.powf(self.config.excitement_curve)
+    }
+    /// Sets the maximum velocity of every boid from the excitement (and the sprint, for the
+    /// sprinting boid).
+    fn update_speed_scales(&mut self) {
+        let scale = self.excitement_scale(self.config.excitement_speed);
+        for (idx, grid_boid) in self.boids.iter_mut().enumerate() {
+            let sprint = if self.sprinter == Some(idx) {
+                SPRINT_MULTIPLIER
+            } else {
+                1.
+            };
+            grid_boid.boid.set_max_speed_scale(sprint * scale);
+        }
+    }
+
+    /// Selects the boids inside the given rectangle, instead of the boids that were selected before.
+    pub fn select_boids(&mut self, area: Rect) {
+        self.selection = self.boids_in_rect(area).into_iter().collect();
+        // Deselecting the traced boid stops the trace:
+        if self
+            .traced
+            .is_some_and(|idx| !self.selection.contains(&idx))
+        {
+            self.stop_trace();
+        }
+    }
+    /// Changes the species (and therefore the color) of all selected boids to the given species.
+    pub fn recolor_selection(&mut self, species: usize) {
+        for &idx in self.selection.iter() {
+            self.boids[idx].boid.set_species(species);
+        }
+        // Groups are split by color, so contact times are no longer valid:
+        self.group_contacts.clear();
+    }
+    /// Returns an iterator over the selected boids, in no particular order.
+    pub fn selected_boids(&self) -> impl Iterator<Item = &Boid> {
+        self.selection.iter().map(|&idx| &self.boids[idx].boid)
+    }
+
+    /// Starts tracing the rules of the selected boid, if exactly one boid is selected. Returns
+    /// whether a boid is traced.
+    pub fn trace_selection(&mut self) -> bool {
+        self.stop_trace();
+        if self.selection.len() == 1 {
+            self.traced = self.selection.iter().next().copied();
+        }
+        self.traced.is_some()
+    }
+    /// Stops tracing the traced boid (if any).
+    pub fn stop_trace(&mut self) {
+        self.traced = None;
+        self.trace = None;
+    }
+    /// Returns whether a boid is traced.
+    pub fn is_tracing(&self) -> bool {
+        self.traced.is_some()
+    }
+    /// Returns the traced boid and what steered it in the last step, if a boid is traced and the
+    /// simulation stepped since the trace started.
+    pub fn traced_boid(&self) -> Option<(BoidView, RuleTrace)> {
+        let idx = self.traced?;
+        Some((BoidView::new(&self.boids[idx]), self.trace?))
+    }
+
+    /// Returns the direction every rule gave the boid in the given index in the last step, in the
+    /// order the rules are calculated (see `RuleTrace`), if there's such a boid and the simulation
+    /// stepped since the boids were replaced. Boids added since the last step got no directions.
+    pub fn last_rule_contributions(&self, idx: usize) -> Option<[Vec2; RULE_COUNT]> {
+        if idx >= self.boids.len() || self.rule_contributions.len() != RULE_COUNT {
+            return None;
+        }
+        Some(std::array::from_fn(|rule| {
+            self.rule_contributions[rule][idx]
+        }))
+    }
+
+    /// Splits the flock in two along the line through its centroid that's perpendicular to its
+    /// heading. For the next `SPLIT_DURATION` steps, the boids on each side of the line are pushed
+    /// away from the other side, and then they're released.
+    pub fn split_flock(&mut self) {
+        let heading = self
+            .boids()
+            .map(|boid| boid.speed())
+            .sum::<Vec2>()
+            .try_normalize()
+            .unwrap_or(Vec2::X);
+        let centroid = self.centroid;
+        self.split_pushes = self
+            .boids()
+            .map(|boid| {
+                if (boid.pos() - centroid).dot(heading) >= 0. {
+                    SPLIT_FORCE * heading
+                } else {
+                    -SPLIT_FORCE * heading
+                }
+            })
+            .collect();
+        self.split_steps_left = SPLIT_DURATION;
+    }
+
+    pub fn target(&self) -> Option<Vec2> {
+        self.target
+    }
+    pub fn set_target(&mut self, target: Option<Vec2>) {
+        self.target = target;
+    }
+
+    pub fn restrict_walls(&self) -> bool {
+        self.restrict_walls
+    }
+    pub fn set_restrict_walls(&mut self, restrict_walls: bool) {
+        self.restrict_walls = restrict_walls;
+    }
+
+    /// Returns the polygon the boids are confined to, if there is one.
+    pub fn boundary(&self) -> Option<&Boundary> {
+        self.boundary.as_ref()
+    }
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(obstacle);
+    }
+    /// Removes every obstacle containing the given point, and returns whether any were removed.
+    pub fn remove_obstacles_at(&mut self, point: Vec2) -> bool {
+        let count = self.obstacles.len();
+        self.obstacles.retain(|obstacle| !obstacle.contains(point));
+        self.obstacles.len() != count
+    }
+
+    pub fn calm_zones(&self) -> &[CalmZone] {
+        &self.calm_zones
+    }
+    pub fn add_calm_zone(&mut self, calm_zone: CalmZone) {
+        self.calm_zones.push(calm_zone);
+    }
+    /// Removes every calm zone containing the given point, and returns whether any were removed.
+    pub fn remove_calm_zones_at(&mut self, point: Vec2) -> bool {
+        let count = self.calm_zones.len();
+        self.calm_zones
+            .retain(|calm_zone| !calm_zone.contains(point));
+        self.calm_zones.len() != count
+    }
+
+    pub fn influencers(&self) -> &[Influencer] {
+        &self.influencers
+    }
+    /// Adds the given influencer, unless there are already `MAX_INFLUENCERS` influencers. Returns
+    /// whether it was added.
+    pub fn add_influencer(&mut self, influencer: Influencer) -> bool {
+        if self.influencers.len() >= MAX_INFLUENCERS {
+            return false;
+        }
+        self.influencers.push(influencer);
+        true
+    }
+    /// Removes every influencer up to the given distance from the given point, and returns whether
+    /// any were removed.
+    pub fn remove_influencers_near(&mut self, point: Vec2, distance: f32) -> bool {
+        let count = self.influencers.len();
+        self.influencers
+            .retain(|influencer| influencer.pos.distance_squared(point) > distance * distance);
+        self.influencers.len() != count
+    }
+
+    pub fn reefs(&self) -> &[Reef] {
+        &self.reefs
+    }
+    pub fn add_reef(&mut self, reef: Reef) {
+        self.reefs.push(reef);
+    }
+    /// Removes every reef containing the given point, and returns whether any were removed.
+    pub fn remove_reefs_at(&mut self, point: Vec2) -> bool {
+        let count = self.reefs.len();
+        self.reefs.retain(|reef| !reef.contains(point));
+        self.reefs.len() != count
+    }
+
+    pub fn predators(&self) -> &[Predator] {
+        &self.predators
+    }
+    /// Adds a predator at the given position, heading in a random direction.
+    pub fn add_predator(&mut self, pos: Vec2) {
+        let predator = Predator::new(pos, &mut self.rng);
+        self.predators.push(predator);
+    }
+    /// Adds the given predator to the simulation.
+    pub fn insert_predator(&mut self, predator: Predator) {
+        self.predators.push(predator);
+    }
+    /// Removes every predator up to the given distance from the given point, and returns whether
+    /// any were removed.
+    pub fn remove_predators_near(&mut self, point: Vec2, distance: f32) -> bool {
+        let count = self.predators.len();
+        self.predators
+            .retain(|predator| predator.pos().distance_squared(point) > distance * distance);
+        self.predators.len() != count
+    }
+
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+    /// Replaces the parameters of the simulation. The boids themselves are not affected.
+    pub fn set_config(&mut self, config: SimConfig) {
+        self.threads = worker_threads(&config);
+        self.boundary = boundary_of(&config);
+        self.config = config;
+    }
+
+    /// Returns whether the neighbors found using the location grid in the last step matched the ones
+    /// found by checking every pair of boids, or `None` if `verify_neighbors` isn't set.
+    pub fn neighbors_match(&self) -> Option<bool> {
+        self.neighbors_match
+    }
+
+    /// Returns the average position of all boids. If there are no boids, `Vec2::ZERO` is returned.
+    pub fn centroid(&self) -> Vec2 {
+        self.centroid
+    }
+
+    /// Returns the smallest rectangle containing all boids. If there are no boids, an empty
+    /// rectangle is returned.
+    pub fn bounding_box(&self) -> Rect {
+        self.bounding_box
+    }
+
+    /// Returns the number of steps the simulation ran.
+    pub fn step_count(&self) -> u64 {
+        self.steps
+    }
+    /// Sets the number of steps the simulation ran, and the simulated time to as long as they take.
+    pub fn set_step_count(&mut self, steps: u64) {
+        self.steps = steps;
+        self.time = steps as f64 * STEP_DURATION;
+    }
+    /// Runs the given function on the simulation after every step that brings the step count to a
+    /// multiple of `interval` (e.g: an interval of 300 runs it after steps 300, 600 and so on),
+    /// replacing the function set before. This scripts events (like placing a predator or a target) without
+    /// the simulation knowing about them. The function must not step the simulation itself.
+    pub fn set_tick_hook(
+        &mut self,
+        interval: NonZeroU64,
+        hook: impl FnMut(&mut Simulation) + Send + 'static,
+    ) {
+        self.tick_hook = Some((interval, Arc::new(Mutex::new(hook))));
+    }
+
+    /// Returns the simulated time (in seconds) the simulation ran for.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Returns how aligned the boids' directions are, from 0 (boids go in all directions) to 1 (all
+    /// boids go in the same direction). If there are no boids, 0 is returned.
+    pub fn polarization(&self) -> f32 {
+        if self.boids.is_empty() {
+            return 0.;
+        }
+        let sum: Vec2 = self
+            .boids
+            .iter()
+            .map(|grid_boid| grid_boid.boid.speed().normalize_or_zero())
+            .sum();
+        sum.length() / self.boids.len() as f32
+    }
+
+    /// Returns statistics about the groups the boids are split into. Boids are in the same group if
+    /// they share a color and are connected by a chain of boids up to `group_distance` away from
+    /// each other.
+    pub fn group_stats(&self) -> GroupStats {
+        GroupStats::from_representatives(&self.calc_groups(self.config.group_distance))
+    }
+
+    /// Returns the corners of the convex hull around all boids (see `convex_hull`).
+    pub fn flock_hull(&self) -> Vec<Vec2> {
+        convex_hull(self.boids().map(|boid| boid.pos()).collect())
+    }
+    /// Returns the corners of the convex hull around every group of at least `HULL_MIN_GROUP_SIZE`
+    /// boids (groups are found like in `group_stats`), with the group's color.
+    pub fn group_hulls(&self) -> Vec<(Vec<Vec2>, Rgba)> {
+        // Groups are listed by their representatives, so they're always in the same order:
+        let mut members = vec![Vec::new(); self.boids.len()];
+        for (idx, representative) in self
+            .calc_groups(self.config.group_distance)
+            .into_iter()
+            .enumerate()
+        {
+            members[representative].push(self.boids[idx].boid.pos());
+        }
+        members
+            .into_iter()
+            .enumerate()
+            .filter(|(_, positions)| positions.len() >= HULL_MIN_GROUP_SIZE)
+            .map(|(representative, positions)| {
+                (
+                    convex_hull(positions),
+                    self.boids[representative].boid.color(),
+                )
+            })
+            .collect()
+    }
+
+    /// Fades the trails and has every boid deposit a trail in its cell.
+    fn update_trail(&mut self) {
+        let (deposit, decay) = (self.config.trail_deposit, self.config.trail_decay);
+        for cell in self.trail.iter_mut() {
+            *cell *= 1. - decay;
+        }
+        if deposit > 0. {
+            for grid_boid in self.boids.iter() {
+                self.trail[grid_boid.row * self.grid_width + grid_boid.col] += deposit;
+            }
+        }
+    }
+
+    /// Returns the area of every cell of the grid along with the trail left in it.
+    pub fn trail_cells(&self) -> impl Iterator<Item = (Rect, f32)> + '_ {
+        self.trail.iter().enumerate().map(|(idx, &trail)| {
+            let (row, col) = (idx / self.grid_width, idx % self.grid_width);
+            (Self::cell_rect(row, col), trail)
+        })
+    }
+
+    /// Returns the area of every cell of the grid along with the number of boids in it.
+    pub fn cell_occupancy(&self) -> impl Iterator<Item = (Rect, usize)> + '_ {
+        self.location_grid
+            .iter()
+            .enumerate()
+            .flat_map(|(row, cells)| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(move |(col, cell)| (Self::cell_rect(row, col), cell.len()))
+            })
+    }
+
+    /// Returns what the location grid holds where the given point is, for debugging the neighbor
+    /// search: the cell the point maps to (the same way boids are put in cells), the boids in it and
+    /// the cells scanned for the close boids of a boid in it. Points outside the grid don't map to
+    /// any cell.
+    pub fn inspect_cell(&self, point: Vec2) -> Option<CellInspection> {
+        let grid_end =
+            INFLUENCE_DISTANCE * Vec2::new(self.grid_width as f32, self.grid_height as f32);
+        if !(0. ..grid_end.x).contains(&point.x) || !(0. ..grid_end.y).contains(&point.y) {
+            return None;
+        }
+        let (row, col) = cell_of(point, self.grid_width, self.grid_height);
+
+        let mut neighborhood = Vec::new();
+        run_for_neighbor_cells(
+            row,
+            col,
+            self.grid_width,
+            self.grid_height,
+            self.config.neighbor_rings,
+            |row, col| neighborhood.push((row, col)),
+        );
+        Some(CellInspection {
+            row,
+            col,
+            boids: self.location_grid[row][col].clone(),
+            neighborhood,
+        })
+    }
+
+    /// Returns the area of the grid cell in the given row and column.
+    pub fn cell_rect(row: usize, col: usize) -> Rect {
+        Rect::new(
+            col as f32 * INFLUENCE_DISTANCE,
+            row as f32 * INFLUENCE_DISTANCE,
+            INFLUENCE_DISTANCE,
+            INFLUENCE_DISTANCE,
+        )
+    }
+
+    /// Recalculates the centroid and bounding box of the flock.
+    fn update_flock_bounds(&mut self) {
+        if self.boids.is_empty() {
+            (self.centroid, self.bounding_box) = (Vec2::ZERO, Rect::default());
+            return;
+        }
+
+        let (mut min, mut max) = (Vec2::MAX, Vec2::MIN);
+        let mut sum = Vec2::ZERO;
+        for grid_boid in self.boids.iter() {
+            let pos = grid_boid.boid.pos();
+            (min, max) = (min.min(pos), max.max(pos));
+            sum += pos;
+        }
+        self.centroid = sum / self.boids.len() as f32;
+        self.bounding_box = Rect::new(min.x, min.y, max.x - min.x, max.y - min.y);
+    }
+
+    /// Returns an iterator over the indices of the boids in the given grid cell.
+    /// In reduced accuracy mode, crowded cells only yield a sample of their boids.
+    fn cell_boids(&self, row: usize, col: usize) -> impl Iterator<Item = &usize> {
+        self.cell_sample(&self.location_grid[row][col], true)
+    }
+
+    /// Returns an iterator over the given cell's boids, or over a sample of them if `sampled` is set
+    /// and the cell is crowded in reduced accuracy mode. The sample is spread evenly over the cell,
+    /// starting from an offset that moves every step, so the boids that entered the cell first
+    /// aren't the only ones ever looked at.
+    fn cell_sample<'a>(
+        &self,
+        cell: &'a [usize],
+        sampled: bool,
+    ) -> impl Iterator<Item = &'a usize> + 'a {
+        let len = cell.len();
+        let count = if sampled { self.sample_size(len) } else { len };
+        let offset = if count < len {
+            self.steps as usize % len
+        } else {
+            0
+        };
+        (0..count).map(move |i| &cell[(i * len / count + offset) % len])
+    }
+
+    /// Returns the number of boids looked at in a cell with the given number of boids, which is
+    /// all of them unless the cell is crowded in reduced accuracy mode.
+    fn sample_size(&self, cell_size: usize) -> usize {
+        if self.config.reduced_accuracy && cell_size > self.config.density_threshold {
+            self.config.neighbor_samples
+        } else {
+            cell_size
+        }
+    }
+
+    fn get_random_boids(
+        rng: &mut StdRng,
+        size: Vec2,
+        margin: f32,
+        config: &SimConfig,
+    ) -> (Vec<Vec<Vec<usize>>>, Vec<GridBoid>) {
+        // Create the location grid:
+        let (grid_width, grid_height) = grid_size(size);
+        let mut location_grid = vec![vec![Vec::new(); grid_width]; grid_height];
+
+        // Keep the same density of boids as on the full screen:
+        let boids_num = ((BOIDS_NUM as f32 * size.x * size.y / (SCREEN_WIDTH * SCREEN_HEIGHT))
+            as usize)
+            .min(config.max_boids);
+
+        // Split the boids between the species:
+        let species: Vec<usize> = species_counts(&config.species_proportions, boids_num)
+            .into_iter()
+            .enumerate()
+            .flat_map(|(species, count)| std::iter::repeat_n(species, count))
+            .collect();
+
+        // Create boids (position them at the center of each location cell):
+        let boids: Vec<GridBoid> = (0..boids_num)
+            .map(|i| {
+                // Create boid with its species, heading as if spawned around the center:
+                let x = rng.gen_range(margin..size.x - margin);
+                let y = rng.gen_range(margin..size.y - margin);
+                let speed = random_speed(rng, config.spawn_heading, Vec2::new(x, y) - size / 2.);
+                let boid = Boid::new(x, y, speed, species[i]);
+
+                // Calculate row and column:
+                let (row, col) = cell_of(boid.pos(), grid_width, grid_height);
+
+                // Change add index to location grid:
+                location_grid[row][col].push(i);
+
+                // Return GridBoid:
+                GridBoid {
+                    boid,
+                    row,
+                    col,
+                    id: i as u64,
+                    memory: Vec::new(),
+                    panic: 0.,
+                    panic_dir: Vec2::ZERO,
+                }
+            })
+            .collect();
+
+        (location_grid, boids)
+    }
+
+    /// Advances the simulation by a single step.
+    pub fn step(&mut self) {
+        // Check the neighbor search:
+        self.neighbors_match = self
+            .config
+            .verify_neighbors
+            .then(|| self.verify_neighbors());
+
+        // Merge touching flocks:
+        if self.config.merge_on_contact {
+            self.merge_touching_groups();
+        }
+
+        // Update directions (after moving the point boids follow along with the leader):
+        if self.handoff_steps_left > 0 {
+            self.handoff_steps_left -= 1;
+        }
+        self.update_leader_pos();
+        self.update_group_leaders();
+        self.update_neighbor_memory();
+        self.update_panic();
+        self.update_speed_scales();
+        self.update_boids_directions();
+
+        // Release the halves of a split flock once the split is over:
+        if self.split_steps_left > 0 {
+            self.split_steps_left -= 1;
+            if self.split_steps_left == 0 {
+                self.split_pushes.clear();
+            }
+        }
+
+        // Move boids (boids inside the time lens only move part of the way), removing the ones that
+        // left through open edges (from the last one, to keep the other indices valid):
+        let (size, edges) = (self.size, self.config.edges);
+        let time_scales = self.time_scales();
+        let left: Vec<usize> = self
+            .boids
+            .iter_mut()
+            .zip(time_scales)
+            .enumerate()
+            .filter_map(|(i, (grid_boid, time_scale))| {
+                (!grid_boid.boid.go_forward(size, edges, time_scale)).then_some(i)
+            })
+            .collect();
+        for idx in left.into_iter().rev() {
+            self.remove_boid(idx);
+        }
+
+        // Move the predators after their prey:
+        self.hunt();
+
+        // Let new boids flow in:
+        self.flow_in();
+
+        // Push boids that got too close to each other apart:
+        if self.config.min_distance > 0. {
+            self.recalculate_boid_indices();
+            self.enforce_min_distance();
+        }
+
+        // Push boids that went into obstacles back out of them:
+        self.resolve_obstacle_collisions();
+
+        // Recalculate indices (so the grid is up to date between steps):
+        self.recalculate_boid_indices();
+
+        // Leave trails where the boids are now:
+        self.update_trail();
+
+        // Turn the boids' drawn headings after their velocities:
+        let smoothing = self.config.heading_smoothing;
+        for grid_boid in self.boids.iter_mut() {
+            grid_boid.boid.smooth_heading(smoothing);
+        }
+
+        // Update the flock's centroid and bounding box:
+        self.update_flock_bounds();
+        self.steps += 1;
+        self.time += STEP_DURATION;
+
+        // Run the script once every interval:
+        if let Some((interval, hook)) = &self.tick_hook {
+            if self.steps.is_multiple_of(interval.get()) {
+                let hook = Arc::clone(hook);
+                (hook.lock().expect("Error in the tick hook"))(self);
+            }
+        }
+    }
+
+    /// Makes every boid forget the neighbors it hasn't seen in `neighbor_memory` steps, and remember
+    /// the neighbors of its species that are in range now for that many steps.
+    fn update_neighbor_memory(&mut self) {
+        let memory = self.config.neighbor_memory;
+        if memory == 0 {
+            // Forget everything if the memory was just turned off:
+            if !self.id_indices.is_empty() {
+                self.id_indices.clear();
+                for grid_boid in self.boids.iter_mut() {
+                    grid_boid.memory.clear();
+                }
+            }
+            return;
+        }
+
+        for grid_boid in self.boids.iter_mut() {
+            grid_boid.memory.retain_mut(|(_, steps)| {
+                *steps -= 1;
+                *steps > 0
+            });
+        }
+        let mut seen = Vec::new();
+        self.for_each_close_pair(INFLUENCE_DISTANCE_SQUARED, false, |i, j| {
+            if self.same_species(i, j) {
+                seen.push((i, j));
+            }
+        });
+        for (i, j) in seen {
+            for (this, other) in [(i, j), (j, i)] {
+                let id = self.boids[other].id;
+                let memory_entries = &mut self.boids[this].memory;
+                match memory_entries.iter_mut().find(|(known, _)| *known == id) {
+                    Some((_, steps)) => *steps = memory,
+                    None => memory_entries.push((id, memory)),
+                }
+            }
+        }
+
+        self.id_indices = self
+            .boids
+            .iter()
+            .enumerate()
+            .map(|(idx, grid_boid)| (grid_boid.id, idx))
+            .collect();
+    }
+
+    /// Returns the boids the given boid remembers that are out of its range now, each with how much
+    /// of the memory is left (from 1 just after it went out of range, down towards 0). Boids that
+    /// were removed since are skipped.
+    fn remembered_neighbors<'a>(
+        &'a self,
+        this: &'a GridBoid,
+    ) -> impl Iterator<Item = (&'a GridBoid, f32)> + 'a {
+        let memory = self.config.neighbor_memory as f32;
+        this.memory.iter().filter_map(move |&(id, steps)| {
+            let other = &self.boids[*self.id_indices.get(&id)?];
+            let distance_squared = this.boid.pos().distance_squared(other.boid.pos());
+            (distance_squared > INFLUENCE_DISTANCE_SQUARED)
+                .then_some((other, steps as f32 / memory))
+        })
+    }
+
+    /// Runs the given function for every pair of boids whose squared distance is at most
+    /// `max_distance_squared`. Each pair is given once, as (smaller index, larger index).
+    /// The maximum distance must not exceed `INFLUENCE_DISTANCE` (the size of a grid cell).
+    /// If `sampled` is set, crowded cells are sampled in reduced accuracy mode (see `cell_boids`).
+    fn for_each_close_pair<F>(&self, max_distance_squared: f32, sampled: bool, f: F)
+    where
+        F: FnMut(usize, usize),
+    {
+        let rings = self.config.neighbor_rings;
+        self.for_each_close_pair_in_rings(max_distance_squared, sampled, rings, f);
+    }
+
+    /// Like `for_each_close_pair`, but only looks for pairs up to the given number of rings of cells
+    /// away from each other (e.g: zero only gives pairs in the same cell).
+    fn for_each_close_pair_in_rings<F>(
+        &self,
+        max_distance_squared: f32,
+        sampled: bool,
+        rings: usize,
+        mut f: F,
+    ) where
+        F: FnMut(usize, usize),
+    {
+        for (i, this) in self.boids.iter().enumerate() {
+            run_for_neighbor_cells(
+                this.row,
+                this.col,
+                self.grid_width,
+                self.grid_height,
+                rings,
+                |row, col| {
+                    for &other_idx in self.cell_sample(&self.location_grid[row][col], sampled) {
+                        // Only check every pair once:
+                        if other_idx <= i {
+                            continue;
+                        }
+                        let other = &self.boids[other_idx];
+                        if this.boid.pos().distance_squared(other.boid.pos())
+                            <= max_distance_squared
+                        {
+                            f(i, other_idx);
+                        }
+                    }
+                },
+            );
+        }
+    }
+
+    /// Compares the close boids found using the location grid to the ones found by checking every
+    /// pair of boids (which is slow but can't miss), printing every pair the grid missed.
+    /// Returns whether both methods found the same boids.
+    fn verify_neighbors(&self) -> bool {
+        let mut grid_pairs = HashSet::new();
+        self.for_each_close_pair(INFLUENCE_DISTANCE_SQUARED, false, |i, j| {
+            grid_pairs.insert((i, j));
+        });
+
+        let mut matches = true;
+        for (i, this) in self.boids.iter().enumerate() {
+            for (j, other) in self.boids.iter().enumerate().skip(i + 1) {
+                let distance_squared = this.boid.pos().distance_squared(other.boid.pos());
+                if distance_squared <= INFLUENCE_DISTANCE_SQUARED && !grid_pairs.contains(&(i, j)) {
+                    let distance = distance_squared.sqrt();
+                    eprintln!("Location grid missed boids {i} and {j} ({distance} apart)");
+                    matches = false;
+                }
+            }
+        }
+        matches
+    }
+
+    /// Splits the boids into groups of boids who share a color and are connected by a chain of
+    /// boids within the given distance of each other (i.e: flocks). The distance must not exceed
+    /// `INFLUENCE_DISTANCE`.
+    /// Returns the representative of each boid's group, which is the group's boid with the smallest
+    /// index.
+    fn calc_groups(&self, distance: f32) -> Vec<usize> {
+        let mut groups = DisjointSet::new(self.boids.len());
+        self.for_each_close_pair(distance * distance, false, |i, j| {
+            if self.same_species(i, j) {
+                groups.union(i, j);
+            }
+        });
+        groups.into_representatives()
+    }
+
+    /// Recolors groups that have been touching a group of a different color for long enough. The
+    /// smaller group of the two takes the color of the larger one.
+    fn merge_touching_groups(&mut self) {
+        // Split the boids to groups and calculate the size of each group:
+        let groups = self.calc_groups(INFLUENCE_DISTANCE);
+        let mut sizes = vec![0usize; self.boids.len()];
+        for &group in groups.iter() {
+            sizes[group] += 1;
+        }
+
+        // Find the differently colored groups that are touching each other:
+        let mut touching = HashSet::new();
+        self.for_each_close_pair(STEERING_DISTANCE_SQUARED, false, |i, j| {
+            if !self.same_species(i, j) {
+                touching.insert((groups[i].min(groups[j]), groups[i].max(groups[j])));
+            }
+        });
+
+        // Groups that stopped touching lose their contact time:
+        let contacts: HashMap<(usize, usize), u32> = touching
+            .into_iter()
+            .map(|pair| (pair, self.group_contacts.get(&pair).unwrap_or(&0) + 1))
+            .collect();
+        self.group_contacts = contacts;
+
+        // Merge the groups that have been touching long enough:
+        let mut merging: Vec<(usize, usize)> = self
+            .group_contacts
+            .iter()
+            .filter(|(_, &steps)| steps >= self.config.merge_contact_steps)
+            .map(|(&pair, _)| pair)
+            .collect();
+        // The map's order is random, so merge in a fixed order to keep seeded runs reproducible:
+        merging.sort_unstable();
+        for (a, b) in merging {
+            self.group_contacts.remove(&(a, b));
+            let (winner, loser) = if sizes[a] >= sizes[b] { (a, b) } else { (b, a) };
+            let species = self.boids[winner].boid.species();
+            for (grid_boid, &group) in self.boids.iter_mut().zip(groups.iter()) {
+                if group == loser {
+                    grid_boid.boid.set_species(species);
+                }
+            }
+        }
+    }
+
+    fn update_boids_directions(&mut self) {
+        // Without boids there's nothing to calculate (and no reason to start threads):
+        if self.boids.is_empty() {
+            return;
+        }
+
+        // Calculate new directions for each boid based on these rules:
+        // 1) Don't go towards other boids (Separation).
+        // 2) Align direction with close boids' direction (Alignment).
+        // 3) Go towards the average location of close boids (Cohesion).
+        // 4) Avoid screen walls (Evasion).
+        // 5) Go towards the target, the leader and the center of the screen (if set).
+        // 6) Flee from close predators.
+        // 7) Follow trails.
+        // 8) Go towards attractors and away from repellers.
+        // 9) Go towards the centers of reefs.
+        // With the potential spacing model, a single force replaces separation and cohesion:
+        let potential = matches!(self.config.spacing_model, SpacingModel::Potential { .. });
+        let separation = || {
+            if potential {
+                self.calc_potential_directions()
+            } else {
+                self.calc_separation_directions()
+            }
+        };
+        let cohesion = || {
+            if potential {
+                vec![Vec2::ZERO; self.boids.len()]
+            } else {
+                self.calc_cohesion_directions()
+            }
+        };
+        let alignment = || self.calc_alignment_directions();
+        let evasion = || self.calc_evasion_directions();
+        let target = || self.calc_target_directions();
+        let leader = || self.calc_leader_directions();
+        let center = || self.calc_center_directions();
+        let flee = || self.calc_flee_directions();
+        let trail = || self.calc_trail_directions();
+        let influencers = || self.calc_influencer_directions();
+        let reefs = || self.calc_reef_directions();
+        let rules: [(&str, &(dyn Fn() -> Vec<Vec2> + Sync)); RULE_COUNT - 1] = [
+            ("separation", &separation),
+            ("alignment", &alignment),
+            ("cohesion", &cohesion),
+            ("evasion", &evasion),
+            ("target", &target),
+            ("leader", &leader),
+            ("center", &center),
+            ("flee", &flee),
+            ("trail", &trail),
+            ("influencers", &influencers),
+            ("reefs", &reefs),
+        ];
+
+        // Rules are only recalculated once every their interval, on steps shifted by their index so
+        // the rules recalculated less often don't all land on the same steps. Rules without
+        // directions to reuse (e.g: after boids were replaced) are recalculated anyway:
+        let intervals = self.config.rule_intervals;
+        let due: Vec<usize> = (0..rules.len())
+            .filter(|&rule| {
+                self.rule_cache[rule].len() != self.boids.len()
+                    || (self.steps + rule as u64).is_multiple_of(intervals[rule])
+            })
+            .collect();
+        let due_rules: Vec<_> = due.iter().map(|&rule| rules[rule]).collect();
+
+        // Split the rules between the worker threads, or calculate them one after the other in this
+        // thread (the rules only read the simulation, so both give the same directions):
+        let calculated: Vec<Vec<Vec2>> = if self.config.parallel && !due_rules.is_empty() {
+            // Consecutive rules share a thread, so the directions stay in the rules' order:
+            let chunk_size = due_rules.len().div_ceil(self.threads.min(due_rules.len()));
+            crossbeam::thread::scope(|s| {
+                let threads: Vec<_> = due_rules
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        s.spawn(move |_| chunk.iter().map(|&(_, rule)| rule()).collect::<Vec<_>>())
+                    })
+                    .collect();
+
+                // Join all threads and put in a vector:
+                threads
+                    .into_iter()
+                    .zip(due_rules.chunks(chunk_size))
+                    .flat_map(|(thread, chunk)| {
+                        thread.join().unwrap_or_else(|_| {
+                            let names: Vec<_> = chunk.iter().map(|(name, _)| *name).collect();
+                            panic!("Error in {} thread", names.join("/"))
+                        })
+                    })
+                    .collect()
+            })
+            .expect("Error creating threads")
+        } else {
+            due_rules.iter().map(|&(_, rule)| rule()).collect()
+        };
+        let rule_names = rules.map(|(name, _)| name);
+
+        // Keep the new directions for the steps the rules aren't recalculated on, unless every rule
+        // is recalculated on every step anyway:
+        let mut directions_matrix = if intervals.iter().all(|&interval| interval == 1) {
+            self.rule_cache.iter_mut().for_each(Vec::clear);
+            calculated
+        } else {
+            for (rule, directions) in due.into_iter().zip(calculated) {
+                self.rule_cache[rule] = directions;
+            }
+            self.rule_cache.clone()
+        };
+
+        // Add some noise to break up unnatural formations (this uses the simulation's random number
+        // generator, so it can't be calculated in a different thread):
+        directions_matrix.push(self.calc_noise_directions());
+
+        // Excited boids keep farther apart:
+        let separation_scale = self.excitement_scale(self.config.excitement_separation);
+        if separation_scale != 1. {
+            for direction in directions_matrix[0].iter_mut() {
+                *direction *= separation_scale;
+            }
+        }
+
+        // Boids inside calm zones only get part of the rules' steering:
+        let calm_scales: Vec<f32> = self
+            .boids
+            .iter()
+            .map(|grid_boid| {
+                let pos = grid_boid.boid.pos();
+                if self.calm_zones.iter().any(|zone| zone.contains(pos)) {
+                    self.config.calm_factor
+                } else {
+                    1.
+                }
+            })
+            .collect();
+
+        // Tethered boids are pulled by the tether's spring, the halves of a split flock are pushed
+        // apart and the sprinting boid is pushed forward, however calm they are:
+        let mut pulls = if self.split_pushes.is_empty() {
+            vec![Vec2::ZERO; self.boids.len()]
+        } else {
+            self.split_pushes.clone()
+        };
+        if let Some(anchor) = self.tether_anchor {
+            for &idx in self.tethered.iter() {
+                let boid = &self.boids[idx].boid;
+                pulls[idx] +=
+                    TETHER_STIFFNESS * (anchor - boid.pos()) - TETHER_DAMPING * boid.speed();
+            }
+        }
+        if let Some(idx) = self.sprinter {
+            pulls[idx] += SPRINT_THRUST * self.boids[idx].boid.speed().normalize_or_zero();
+        }
+
+        // Each row in the matrix is a different rule, combine them to a vector with size boids.len
+        // (along with gravity, which affects all boids, the tether, the split and the sprint). The
+        // combined steering is limited so boids can't change their velocity all at once, and boids
+        // inside the time lens only steer by part of it:
+        let max_force = self.config.max_force;
+        let gravity = Vec2::from(self.config.gravity);
+        let time_scales = self.time_scales();
+        let directions_vector: Vec<Vec2> = (0..self.boids.len())
+            .map(|i| {
+                let mut sum = Vec2::ZERO;
+                for rule in directions_matrix.iter() {
+                    sum += rule[i];
+                }
+                time_scales[i]
+                    * (calm_scales[i] * sum + gravity + pulls[i]).clamp_length_max(max_force)
+            })
+            .collect();
+
+        // Keep what every rule contributed, and name the contributions to the traced boid:
+        self.rule_contributions = directions_matrix;
+        if let Some(idx) = self.traced {
+            let pos = self.boids[idx].boid.pos();
+            let directions = self
+                .last_rule_contributions(idx)
+                .expect("Every rule gave the traced boid a direction");
+            self.trace = Some(RuleTrace {
+                neighbors: self.boids_in_radius(pos, INFLUENCE_DISTANCE).len() - 1,
+                contributions: std::array::from_fn(|rule| {
+                    let name = rule_names.get(rule).copied().unwrap_or("noise");
+                    (name, directions[rule])
+                }),
+            });
+        }
+
+        // For each boid, add directions:
+        for (i, direction) in directions_vector.into_iter().enumerate() {
+            self.boids[i].boid.add_dir(direction);
+        }
+
+        self.damp_reef_speeds();
+        self.damp_speeds();
+    }
+
+    /// Slows every boid inside a reef down by the reef damping's share of its velocity, scaled by
+    /// how close it is to the reef's center (the closest reef counts), so boids hover around reefs
+    /// instead of orbiting them.
+    fn damp_reef_speeds(&mut self) {
+        let damping = self.config.reef_damping;
+        if damping == 0. || self.reefs.is_empty() {
+            return;
+        }
+        for grid_boid in self.boids.iter_mut() {
+            let pos = grid_boid.boid.pos();
+            let closeness = self
+                .reefs
+                .iter()
+                .map(|reef| reef.closeness(pos))
+                .fold(0., f32::max);
+            if closeness > 0. {
+                let speed = grid_boid.boid.speed();
+                grid_boid.boid.set_speed((1. - damping * closeness) * speed);
+            }
+        }
+    }
+
+    /// Slows every boid down by the speed damping's share of its velocity if the boids' average
+    /// speed is above the cruise speed, so an agitated flock settles back to cruising.
+    fn damp_speeds(&mut self) {
+        let cruise_speed = self.config.cruise_speed;
+        if cruise_speed == 0. || self.boids.is_empty() {
+            return;
+        }
+        let total_speed: f32 = self
+            .boids
+            .iter()
+            .map(|grid_boid| grid_boid.boid.speed().length())
+            .sum();
+        if total_speed / self.boids.len() as f32 <= cruise_speed {
+            return;
+        }
+        let scale = 1. - self.config.speed_damping;
+        for grid_boid in self.boids.iter_mut() {
+            let speed = grid_boid.boid.speed();
+            grid_boid.boid.set_speed(scale * speed);
+        }
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of small random directions, which prevent
+    /// the flock from settling into rigid lattices. Each direction corresponds to a single boid in
+    /// the `self.boids` vector.
+    /// If the noise factor is zero, all directions are `Vec2::Zero` (and no random numbers are
+    /// generated, so seeded runs without noise stay the same).
+    fn calc_noise_directions(&mut self) -> Vec<Vec2> {
+        let factor = self.config.noise_factor;
+        if factor == 0. {
+            return vec![Vec2::ZERO; self.boids.len()];
+        }
+
+        (0..self.boids.len())
+            .map(|_| factor * Vec2::from_angle(self.rng.gen_range(0.0..std::f32::consts::TAU)))
+            .collect()
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of directions towards the center of the
+    /// screen, which gently keeps the flock on screen while still letting it roam. Each direction
+    /// corresponds to a single boid in the `self.boids` vector.
+    /// If the center pull factor is zero, all directions are `Vec2::Zero`.
+    fn calc_center_directions(&self) -> Vec<Vec2> {
+        let factor = self.config.center_pull_factor;
+        if factor == 0. {
+            return vec![Vec2::ZERO; self.boids.len()];
+        }
+
+        let center = self.size / 2.;
+        self.boids
+            .iter()
+            .map(|grid_boid| factor * (center - grid_boid.boid.pos()))
+            .collect()
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of the steering of all influencers combined.
+    /// Each direction corresponds to a single boid in the `self.boids` vector.
+    /// Without influencers, all directions are `Vec2::Zero`.
+    fn calc_influencer_directions(&self) -> Vec<Vec2> {
+        self.boids
+            .iter()
+            .map(|grid_boid| {
+                self.influencers
+                    .iter()
+                    .map(|influencer| influencer.steering(grid_boid.boid.pos()))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of directions towards the centers of the
+    /// reefs containing the boids. Each direction corresponds to a single boid in the `self.boids`
+    /// vector.
+    /// Without reefs (or without a reef pull), all directions are `Vec2::Zero`.
+    fn calc_reef_directions(&self) -> Vec<Vec2> {
+        let pull = self.config.reef_pull;
+        if pull == 0. || self.reefs.is_empty() {
+            return vec![Vec2::ZERO; self.boids.len()];
+        }
+
+        self.boids
+            .iter()
+            .map(|grid_boid| {
+                let pos = grid_boid.boid.pos();
+                self.reefs
+                    .iter()
+                    .filter(|reef| reef.contains(pos))
+                    .map(|reef| pull * (reef.pos - pos).normalize_or_zero())
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of directions towards stronger trails, along
+    /// the difference between the trails of the cells around each boid's cell (cells outside the
+    /// grid count as having the same trail as the boid's cell). Each direction corresponds to a
+    /// single boid in the `self.boids` vector.
+    /// If the trail follow factor is zero, all directions are `Vec2::Zero`.
+    fn calc_trail_directions(&self) -> Vec<Vec2> {
+        let factor = self.config.trail_follow_factor;
+        if factor == 0. {
+            return vec![Vec2::ZERO; self.boids.len()];
+        }
+
+        let width = self.grid_width;
+        let trail_at = |row: usize, col: usize| self.trail[row * width + col];
+        self.boids
+            .iter()
+            .map(|grid_boid| {
+                let (row, col) = (grid_boid.row, grid_boid.col);
+                let here = trail_at(row, col);
+                let left = if col > 0 {
+                    trail_at(row, col - 1)
+                } else {
+                    here
+                };
+                let right = if col + 1 < width {
+                    trail_at(row, col + 1)
+                } else {
+                    here
+                };
+                let up = if row > 0 {
+                    trail_at(row - 1, col)
+                } else {
+                    here
+                };
+                let down = if row + 1 < self.grid_height {
+                    trail_at(row + 1, col)
+                } else {
+                    here
+                };
+                factor * Vec2::new(right - left, down - up) / 2.
+            })
+            .collect()
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of directions away from close predators.
+    /// Each direction corresponds to a single boid in the `self.boids` vector.
+    /// If there are no predators, all directions are `Vec2::Zero`.
+    /// Boids that don't sense any predator flee in proportion to their panic instead.
+    fn calc_flee_directions(&self) -> Vec<Vec2> {
+        self.boids
+            .iter()
+            .map(|grid_boid| {
+                let flee = self.predator_flee(grid_boid.boid.pos());
+                if flee != Vec2::ZERO {
+                    flee
+                } else {
+                    FLEE_FACTOR * grid_boid.panic * grid_boid.panic_dir
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the direction away from the predators close to the given point (`Vec2::ZERO` if
+    /// there are none).
+    fn predator_flee(&self, pos: Vec2) -> Vec2 {
+        self.predators
+            .iter()
+            .map(|predator| pos - predator.pos())
+            .filter(|offset| offset.length_squared() < FLEE_DISTANCE * FLEE_DISTANCE)
+            .map(|offset| FLEE_FACTOR * offset.normalize_or_zero())
+            .sum()
+    }
+
+    /// Spreads panic through the flock (see PANIC_TRANSMISSION): boids that sense a predator panic
+    /// completely, the panic of the rest fades, and then every boid catches its share of the panic
+    /// of its most panicked neighbor from the previous step.
+    fn update_panic(&mut self) {
+        let transmission = self.config.panic_transmission;
+        if transmission == 0. {
+            // Calm everyone down if panic was just turned off:
+            for grid_boid in self.boids.iter_mut() {
+                grid_boid.panic = 0.;
+            }
+            return;
+        }
+
+        let keep = 1. - self.config.panic_decay;
+        let mut panics: Vec<(f32, Vec2)> = self
+            .boids
+            .iter()
+            .map(|grid_boid| {
+                let flee = self.predator_flee(grid_boid.boid.pos());
+                if flee != Vec2::ZERO {
+                    (1., flee.normalize_or_zero())
+                } else {
+                    (keep * grid_boid.panic, grid_boid.panic_dir)
+                }
+            })
+            .collect();
+        self.for_each_close_pair(INFLUENCE_DISTANCE_SQUARED, false, |i, j| {
+            for (this, other) in [(i, j), (j, i)] {
+                let caught = transmission * self.boids[other].panic;
+                if caught > panics[this].0 {
+                    panics[this] = (caught, self.boids[other].panic_dir);
+                }
+            }
+        });
+        for (grid_boid, (panic, panic_dir)) in self.boids.iter_mut().zip(panics) {
+            grid_boid.panic = panic;
+            grid_boid.panic_dir = panic_dir;
+        }
+    }
+
+    /// Moves every predator towards the closest boid it can see (or lets it wander if it can't see
+    /// any boid).
+    fn hunt(&mut self) {
+        let (fov, range) = (
+            self.config.predator_fov,
+            self.config.predator_detection_range,
+        );
+        let mut caught = Vec::new();
+        for predator in self.predators.iter_mut() {
+            let prey = self
+                .boids
+                .iter()
+                .map(|grid_boid| grid_boid.boid.pos())
+                .filter(|&pos| predator.can_see(pos, fov, range))
+                .min_by(|a, b| {
+                    let a = a.distance_squared(predator.pos());
+                    let b = b.distance_squared(predator.pos());
+                    a.total_cmp(&b)
+                });
+            predator.hunt(prey, &mut self.rng, self.size);
+
+            // Hungry predators catch the closest boid in reach (which no other predator caught),
+            // and are sated by it:
+            if predator.is_sated() {
+                continue;
+            }
+            let catch = self
+                .boids
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| !caught.contains(idx))
+                .map(|(idx, grid_boid)| (idx, grid_boid.boid.pos().distance(predator.pos())))
+                .filter(|&(_, distance)| distance <= PREDATOR_CATCH_DISTANCE)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((idx, _)) = catch {
+                caught.push(idx);
+                predator.sate(self.config.predator_satiation);
+            }
+        }
+
+        // Caught boids are eaten (from the last one, to keep the other indices valid):
+        caught.sort_unstable_by(|a, b| b.cmp(a));
+        for idx in caught {
+            self.remove_boid(idx);
+        }
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of directions towards the target. Each
+    /// direction corresponds to a single boid in the `self.boids` vector.
+    /// If a target is not specified, all directions are `Vec2::Zero`.
+    /// With a front led target, each boid is pulled by the share of its close boids that are
+    /// farther from the target than it, so the boids at the front of every flock are pulled fully
+    /// and the ones at the back aren't pulled at all.
+    fn calc_target_directions(&self) -> Vec<Vec2> {
+        // If there is a target, move the boids towards it:
+        let Some(target_pos) = self.target else {
+            return vec![Vec2::ZERO; self.boids.len()];
+        };
+        if !self.config.front_led_target {
+            return (0..self.boids.len())
+                .map(|i| TARGET_FACTOR * (target_pos - self.boids[i].boid.pos()))
+                .collect();
+        }
+
+        self.boids
+            .iter()
+            .enumerate()
+            .map(|(i, this)| {
+                let distance = this.boid.pos().distance_squared(target_pos);
+                let mut close = 0;
+                let mut behind = 0;
+                run_for_neighbor_cells(
+                    this.row,
+                    this.col,
+                    self.grid_width,
+                    self.grid_height,
+                    self.config.neighbor_rings,
+                    |row, col| {
+                        for &other_idx in self.cell_boids(row, col) {
+                            let other = &self.boids[other_idx];
+                            if i == other_idx
+                                || this.boid.pos().distance_squared(other.boid.pos())
+                                    > INFLUENCE_DISTANCE_SQUARED
+                            {
+                                continue;
+                            }
+                            close += 1;
+                            if other.boid.pos().distance_squared(target_pos) > distance {
+                                behind += 1;
+                            }
+                        }
+                    },
+                );
+                // A boid without close boids is a flock of its own, so it's the front:
+                let front = if close == 0 {
+                    1.
+                } else {
+                    behind as f32 / close as f32
+                };
+                TARGET_FACTOR * front * (target_pos - this.boid.pos())
+            })
+            .collect()
+    }
+
+    /// Moves the point boids follow towards the leader, reaching it when the handoff is over. The
+    /// point starts at the first leader, and without a leader it stays where it was.
+    fn update_leader_pos(&mut self) {
+        let Some(leader) = self.leader().map(Boid::pos) else {
+            return;
+        };
+        self.leader_pos = match self.leader_pos {
+            Some(pos) if self.handoff_steps_left > 0 => {
+                Some(pos + (leader - pos) / self.handoff_steps_left as f32)
+            }
+            _ => Some(leader),
+        };
+    }
+
+    /// With a leader per group (while leaders are on), picks the leader of every group with at least
+    /// `leader_min_group_size` boids: the boid farthest ahead along the group's average heading.
+    fn update_group_leaders(&mut self) {
+        self.group_leaders.clear();
+        if self.config.leader_mode != LeaderMode::PerGroup || self.leader_idx.is_none() {
+            return;
+        }
+
+        let groups = self.calc_groups(self.config.group_distance);
+        let mut sizes = vec![0usize; self.boids.len()];
+        let mut headings = vec![Vec2::ZERO; self.boids.len()];
+        for (grid_boid, &group) in self.boids.iter().zip(groups.iter()) {
+            sizes[group] += 1;
+            headings[group] += grid_boid.boid.speed().normalize_or_zero();
+        }
+
+        // Find the frontmost boid of every large enough group:
+        let mut leaders: Vec<Option<usize>> = vec![None; self.boids.len()];
+        let ahead = |i: usize, group: usize| self.boids[i].boid.pos().dot(headings[group]);
+        for (i, &group) in groups.iter().enumerate() {
+            if sizes[group] < self.config.leader_min_group_size {
+                continue;
+            }
+            if leaders[group].is_none_or(|leader| ahead(i, group) > ahead(leader, group)) {
+                leaders[group] = Some(i);
+            }
+        }
+        self.group_leaders = groups.iter().map(|&group| leaders[group]).collect();
+    }
+
+    /// Returns an iterator over the leaders of the groups, with a leader per group.
+    pub fn group_leaders(&self) -> impl Iterator<Item = &Boid> {
+        self.group_leaders
+            .iter()
+            .enumerate()
+            .filter(|&(i, &leader)| leader == Some(i))
+            .map(|(i, _)| &self.boids[i].boid)
+    }
+
+    /// Calculates a vector of length `self.boids.len()` of directions towards the leader (or the
+    /// leader of each boid's group, with a leader per group). Each direction corresponds to a single
+    /// boid in the `self.boids` vector.
+    /// If a leader is not specified, all directions are `Vec2::Zero`.
+    fn calc_leader_directions(&self) -> Vec<Vec2> {
+        // Repelling leaders push boids away as hard as they'd pull them:
+        let factor = self.leader_sign * LEADER_FACTOR;
+        if self.config.leader_mode == LeaderMode::PerGroup {
+            return (0..self.boids.len())
+                .map(|i| match self.group_leaders.get(i).copied().flatten() {
+                    Some(leader) => {
+                        factor * (self.boids[leader].boid.pos() - self.boids[i].boid.pos())
+                    }
+                    None => Vec2::ZERO,
+                })
+                .collect();
+        }
+
+        // If there is a leader, move the boids towards the point they follow:
+        if let (Some(_), Some(leader_pos)) = (self.leader_idx, self.leader_pos) {
+            (0..self.boids.len())
+                .map(|i| factor * (leader_pos - self.boids[i].boid.pos()))
+                .collect()
+        } else {
+            vec![Vec2::ZERO; self.boids.len()]
+        }
+    }
+
+    /// According to boids' rule of separation, returns a vector containing the directions that
+    /// point away from nearby boids. With drafting, a boid is pushed less by close boids ahead of it
+    /// going the same way (but they're still pushed by it as usual). Separation is deliberately
+    /// species blind (the species relationships don't scale it), so boids don't bump into boids of
+    /// other species even when they ignore them otherwise.
+    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
+    /// vector.
+    fn calc_separation_directions(&self) -> Vec<Vec2> {
+        let mut directions = vec![Vec2::ZERO; self.boids.len()];
+
+        // Every pair is only found once, so the push is calculated once and added to both boids
+        // (in opposite directions):
+        let rings = self
+            .config
+            .separation_scan
+            .rings(self.config.neighbor_rings);
+        self.for_each_close_pair_in_rings(STEERING_DISTANCE_SQUARED, true, rings, |i, j| {
+            let offset = self.pair_offset(i, j);
+            let sub = self.config.separation_curve.apply(offset);
+            directions[i] += self.draft_scale(i, j, offset) * sub;
+            directions[j] -= self.draft_scale(j, i, -offset) * sub;
+        });
+
+        // Don't forget to invert and multiply by factor:
+        directions
+            .into_iter()
+            .map(|dir| -SEPARATION_FACTOR * dir)
+            .collect()
+    }
+
+    /// Returns whether the boids in indices `a` and `b` are of the same species (compared by their
+    /// species index, never by their color).
+    fn same_species(&self, a: usize, b: usize) -> bool {
+        self.boids[a].boid.species() == self.boids[b].boid.species()
+    }
+
+    /// Returns how much the boid `other` influences the alignment and cohesion of the boid `this`:
+    /// the relationship of this boid's species towards the other's, weighted by the other boid's
+    /// speed (relative to the maximum velocity) if influence is speed weighted.
+    fn influence(&self, this: &Boid, other: &Boid) -> f32 {
+        let relationship = self.config.relationship(this.species(), other.species());
+        if self.config.speed_weighted_influence {
+            relationship * other.speed().length() / MAX_BOID_VELOCITY
+        } else {
+            relationship
+        }
+    }
+
+    /// Returns the offset from the boid in index `i` to the boid in index `j`. Boids in exactly the
+    /// same position are considered a unit apart, in a direction picked by the second index (so
+    /// boids stacked together spread out in different directions, and seeded runs stay
+    /// reproducible).
+    fn pair_offset(&self, i: usize, j: usize) -> Vec2 {
+        let offset = self.boids[j].boid.pos() - self.boids[i].boid.pos();
+        if offset.try_normalize().is_some() {
+            offset
+        } else {
+            Vec2::from_angle(j as f32)
+        }
+    }
+
+    /// According to the potential spacing model, returns a vector containing the forces between
+    /// each boid and the boids close to it. Pushes apply between all boids and add up like
+    /// separation, while pulls are weighted by the relationship of the pulled boid's species towards
+    /// the other's and averaged like cohesion (so a boid far inside a large flock isn't crushed by
+    /// the pulls of all of its neighbors).
+    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
+    /// vector.
+    fn calc_potential_directions(&self) -> Vec<Vec2> {
+        let mut pushes = vec![Vec2::ZERO; self.boids.len()];
+        let mut pulls = vec![Vec2::ZERO; self.boids.len()];
+        let mut pull_weights = vec![0f32; self.boids.len()];
+
+        // Every pair is only found once, so the force is calculated once and added to both boids
+        // (in opposite directions):
+        self.for_each_close_pair(INFLUENCE_DISTANCE_SQUARED, true, |i, j| {
+            let offset = self.pair_offset(i, j);
+            let distance = offset.length();
+            let force = self.config.spacing_model.force(distance) * offset / distance;
+            if force.dot(offset) < 0. {
+                pushes[i] += force;
+                pushes[j] -= force;
+            } else {
+                let (this, other) = (self.boids[i].boid.species(), self.boids[j].boid.species());
+                let (weight_i, weight_j) = (
+                    self.config.relationship(this, other),
+                    self.config.relationship(other, this),
+                );
+                pulls[i] += weight_i * force;
+                pulls[j] -= weight_j * force;
+                pull_weights[i] += weight_i.abs();
+                pull_weights[j] += weight_j.abs();
+            }
+        });
+
+        pushes
+            .into_iter()
+            .zip(pulls)
+            .zip(pull_weights)
+            .map(|((push, pull), weight)| push + pull / weight.max(1.))
+            .collect()
+    }
+
+    /// Returns how much of the separation push the boid in index `this` gets from the boid in index
+    /// `other` (given the offset from the first to the second). Without drafting it's always all
+    /// of it, and with drafting the more the other boid is ahead of this one and going the same way,
+    /// the more of the push is removed.
+    fn draft_scale(&self, this: usize, other: usize, offset: Vec2) -> f32 {
+        if !self.config.drafting {
+            return 1.;
+        }
+        let heading = self.boids[this].boid.speed().normalize_or_zero();
+        let ahead = heading.dot(offset.normalize_or_zero()).max(0.);
+        let same_way = heading
+            .dot(self.boids[other].boid.speed().normalize_or_zero())
+            .max(0.);
+        1. - self.config.drafting_allowance * ahead * same_way
+    }
+
+    /// According to boids' rule of alignment, returns a vector containing the difference between
+    /// each boid's current direction and the average direction of boids close to it. Every close
+    /// boid is weighted by its influence on this boid (see `influence`), and only positive
+    /// relationships are considered (by default, only boids sharing this boid's color).
+    /// Depending on the alignment mode, either the velocities or only the headings are averaged (in
+    /// which case the result is scaled by the boid's speed).
+    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
+    /// vector.
+    fn calc_alignment_directions(&self) -> Vec<Vec2> {
+        let mode = self.config.alignment_mode;
+        self.boids
+            .iter()
+            .enumerate()
+            .map(|(i, this)| {
+                // Initialize sum and total weight (this includes our own weight):
+                let mut sum = Vec2::ZERO;
+                let mut total_weight = 1f32;
+
+                // Calculate the average direction of nearby boids:
+                run_for_neighbor_cells(
+                    this.row,
+                    this.col,
+                    self.grid_width,
+                    self.grid_height,
+                    self.config.neighbor_rings,
+                    |row, col| {
+                        for &other_idx in self.cell_boids(row, col) {
+                            // Avoid current boid:
+                            if i == other_idx {
+                                continue;
+                            }
+                            // Check that the distance between boids is within the influence radius
+                            // and that this boid's species is drawn to the other's:
+                            let other = &self.boids[other_idx];
+                            let weight = self.influence(&this.boid, &other.boid);
+                            if this.boid.pos().distance_squared(other.boid.pos())
+                                > INFLUENCE_DISTANCE_SQUARED
+                                || weight <= 0.
+                            {
+                                continue;
+                            }
+
+                            // Add the difference in directions to the weighted average:
+                            sum += weight
+                                * (mode.direction(other.boid.speed())
+                                    - mode.direction(this.boid.speed()));
+                            total_weight += weight;
+                        }
+                    },
+                );
+                // Remembered boids count less the more the memory of them faded:
+                for (other, left) in self.remembered_neighbors(this) {
+                    let weight = left * self.influence(&this.boid, &other.boid);
+                    if weight > 0. {
+                        sum += weight
+                            * (mode.direction(other.boid.speed())
+                                - mode.direction(this.boid.speed()));
+                        total_weight += weight;
+                    }
+                }
+                // If there are no close boids, return 0:
+                if total_weight == 1. {
+                    return Vec2::ZERO;
+                }
+                // Return the difference between the average direction and the boid's direction:
+                let scale = match mode {
+                    AlignmentMode::VelocityAverage => 1.,
+                    AlignmentMode::HeadingAverage => this.boid.speed().length(),
+                };
+                ALIGNMENT_FACTOR * scale * sum / total_weight
+            })
+            .collect()
+    }
+
+    /// According to boids' rule of cohesion, returns a vector containing the difference between
+    /// each boid's current position and the average position of close boids. Every close boid is
+    /// weighted by its influence on this boid (see `influence`), so a negative relationship pushes
+    /// this boid away from the other (by default, only boids sharing this boid's color are
+    /// considered).
+    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
+    /// vector.
+    fn calc_cohesion_directions(&self) -> Vec<Vec2> {
+        // With predictive cohesion, every boid is considered to be where it will be after the
+        // lookahead:
+        let lookahead = if self.config.predictive_cohesion {
+            self.config.cohesion_lookahead
+        } else {
+            0.
+        };
+
+        self.boids
+            .iter()
+            .enumerate()
+            .map(|(i, this)| {
+                // Initialize sum and total weight (this includes our own weight):
+                let mut sum = Vec2::ZERO;
+                let mut total_weight = 1f32;
+
+                // Calculate the average direction of nearby boids:
+                run_for_neighbor_cells(
+                    this.row,
+                    this.col,
+                    self.grid_width,
+                    self.grid_height,
+                    self.config.neighbor_rings,
+                    |row, col| {
+                        for &other_idx in self.cell_boids(row, col) {
+                            // Avoid current boid:
+                            if i == other_idx {
+                                continue;
+                            }
+                            // Check that the distance between boids is within the influence radius
+                            // and that this boid's species cares about the other's:
+                            let other = &self.boids[other_idx];
+                            let weight = self.influence(&this.boid, &other.boid);
+                            if this.boid.pos().distance_squared(other.boid.pos())
+                                > INFLUENCE_DISTANCE_SQUARED
+                                || weight == 0.
+                            {
+                                continue;
+                            }
+
+                            // Add the difference in positions to the weighted average:
+                            let other_pos = other.boid.pos() + lookahead * other.boid.speed();
+                            sum += weight * (other_pos - this.boid.pos());
+                            total_weight += weight.abs();
+                        }
+                    },
+                );
+                // Remembered boids count less the more the memory of them faded:
+                for (other, left) in self.remembered_neighbors(this) {
+                    let weight = left * self.influence(&this.boid, &other.boid);
+                    let other_pos = other.boid.pos() + lookahead * other.boid.speed();
+                    sum += weight * (other_pos - this.boid.pos());
+                    total_weight += weight.abs();
+                }
+
+                // If there are no close boids, return 0:
+                if total_weight == 1. {
+                    return Vec2::ZERO;
+                }
+
+                // Return the difference between the average position and the boid's position:
+                COHESION_FACTOR * sum / total_weight
+            })
+            .collect()
+    }
+
+    /// According to boids' rule of evasion, returns a vector of directions that avoid obstacles.
+    /// Obstacles are the placed obstacles, and the evaded edges of the screen if walls are
+    /// restricted.
+    /// Each direction in the returned vector maps to the boid in the same index in the `boids`
+    /// vector.
+    fn calc_evasion_directions(&self) -> Vec<Vec2> {
+        self.boids
+            .iter()
+            .map(|grid_boid| {
+                // Initialize vector with no evasion:
+                let mut dir = Vec2::ZERO;
+
+                // Go away from close obstacles:
+                let pos = grid_boid.boid.pos();
+                for obstacle in self.obstacles.iter() {
+                    let offset = pos - obstacle.pos;
+                    if offset.length() < obstacle.radius + OBSTACLE_MARGIN {
+                        dir += EVASION_FACTOR * offset.normalize_or_zero();
+                    }
+                }
+
+                if !self.restrict_walls {
+                    return dir;
+                }
+
+                // Stay inside the boundary:
+                if let Some(boundary) = &self.boundary {
+                    if let Some(away) = boundary.evasion(pos, self.margin) {
+                        dir += EVASION_FACTOR * away;
+                    }
+                }
+
+                // Only evaded edges are walls:
+                let [top, right, bottom, left] =
+                    self.config.edges.map(|edge| edge == EdgeBehavior::Evade);
+
+                // Check the walls, by the margin along them or by the boid's whiskers:
+                let (speed, gravity) = (grid_boid.boid.speed(), self.config.gravity);
+                let mut wall_dir = Vec2::ZERO;
+                match self.config.wall_perception {
+                    WallPerception::Margin => {
+                        let evasion = self.config.wall_evasion;
+
+                        // Check floor and ceiling:
+                        if top && pos.y < self.margin {
+                            wall_dir.y = evasion.strength(-speed.y); // Go down
+                        } else if bottom && pos.y > self.size.y - self.margin {
+                            wall_dir.y = -evasion.strength(speed.y); // Go up
+                        }
+
+                        // Check two walls:
+                        if left && pos.x < self.margin {
+                            wall_dir.x = evasion.strength(-speed.x); // Go right
+                        } else if right && pos.x > self.size.x - self.margin {
+                            wall_dir.x = -evasion.strength(speed.x); // Go left
+                        }
+                    }
+                    WallPerception::Whiskers => {
+                        wall_dir = self.whisker_evasion(pos, speed, [top, right, bottom, left]);
+                    }
+                }
+
+                // Walls gravity pulls the boid into hold it up instead:
+                let walls = (top, bottom);
+                if let Some(hold) = self.wall_hold(pos.y, speed.y, self.size.y, gravity[1], walls) {
+                    wall_dir.y = hold;
+                }
+                let walls = (left, right);
+                if let Some(hold) = self.wall_hold(pos.x, speed.x, self.size.x, gravity[0], walls) {
+                    wall_dir.x = hold;
+                }
+
+                // The walls add to the obstacles' evasion instead of replacing it, so a boid isn't
+                // driven into an obstacle by a wall:
+                dir + wall_dir
+            })
+            .collect()
+    }
+
+    /// Returns the evasion of a boid (given its position and speed) from the walls its whiskers hit,
+    /// given which edges (top, right, bottom, left) are walls. For every wall, the closest hit of
+    /// any whisker decides how hard the boid is pushed away from it, from nothing at the end of the
+    /// whiskers to the full evasion right at the wall.
+    fn whisker_evasion(&self, pos: Vec2, speed: Vec2, walls: [bool; 4]) -> Vec2 {
+        let (count, length) = (self.config.whisker_count, self.config.whisker_length);
+        let heading = speed.normalize_or_zero();
+        let [top, right, bottom, left] = walls;
+
+        // How close the closest hit on each wall is (top, right, bottom, left), from 0 to 1:
+        let mut closeness = [0f32; 4];
+        for k in 0..count {
+            // Spread the whiskers evenly, with a single whisker going straight ahead:
+            let angle = if count == 1 {
+                0.
+            } else {
+                WHISKER_SPREAD * (k as f32 / (count - 1) as f32 - 0.5)
+            };
+            let ray = Vec2::from_angle(angle.to_radians()).rotate(heading);
+
+            // Find the distance along the ray to the walls it goes towards:
+            let hits = [
+                (top && ray.y < 0., -pos.y / ray.y),
+                (right && ray.x > 0., (self.size.x - pos.x) / ray.x),
+                (bottom && ray.y > 0., (self.size.y - pos.y) / ray.y),
+                (left && ray.x < 0., -pos.x / ray.x),
+            ];
+            for (wall, (towards, distance)) in hits.into_iter().enumerate() {
+                if towards && distance < length {
+                    closeness[wall] = closeness[wall].max(1. - distance.max(0.) / length);
+                }
+            }
+        }
+
+        // Push away from the walls that were hit, and turn away from them (pushing away alone
+        // can't turn a boid going straight into a wall, it only slows it down):
+        let away: Vec2 = [Vec2::Y, Vec2::NEG_X, Vec2::NEG_Y, Vec2::X]
+            .into_iter()
+            .zip(closeness)
+            .map(|(normal, closeness)| closeness * normal)
+            .sum();
+        let turn = (away - away.dot(heading) * heading)
+            .try_normalize()
+            .unwrap_or(heading.perp());
+        EVASION_FACTOR * (away + away.length() * turn)
+    }
+
+    /// Returns the evasion of a boid from a wall that gravity pulls it into, on a single axis (given
+    /// the boid's position and speed on that axis, the size of the area on it and the gravity on
+    /// it, and whether its low and high edges are walls). Instead of the usual constant push (which
+    /// makes boids bounce on the margin's edge forever), the wall holds the boid up like a damped
+    /// spring, so the flock settles on it.
+    /// Returns `None` if the boid isn't inside the margin of a wall gravity pulls it into.
+    fn wall_hold(
+        &self,
+        pos: f32,
+        speed: f32,
+        size: f32,
+        gravity: f32,
+        (low_wall, high_wall): (bool, bool),
+    ) -> Option<f32> {
+        let depth = if gravity < 0. && low_wall {
+            self.margin - pos
+        } else if gravity > 0. && high_wall {
+            pos - (size - self.margin)
+        } else {
+            return None;
+        };
+        if depth <= 0. {
+            return None;
+        }
+
+        // Cancel gravity, push away from the wall the deeper the boid is and slow the boid down:
+        Some(
+            -gravity
+                - gravity.signum() * EVASION_FACTOR * depth / self.margin
+                - WALL_DAMPING * speed,
+        )
+    }
+
+    /// Pushes every pair of boids closer than the minimum distance apart, symmetrically, until
+    /// they're exactly the minimum distance from each other. This prevents boids from overlapping,
+    /// which both looks bad and makes the separation rule's math degenerate.
+    /// Pairs are pushed one after the other, so pushing one pair apart may push one of its boids
+    /// closer to a third boid (which is fixed in the following steps).
+    fn enforce_min_distance(&mut self) {
+        let min_distance = self.config.min_distance;
+        let mut pairs = Vec::new();
+        self.for_each_close_pair(min_distance * min_distance, false, |i, j| {
+            pairs.push((i, j))
+        });
+
+        for (i, j) in pairs {
+            let offset = self.boids[j].boid.pos() - self.boids[i].boid.pos();
+            let distance = offset.length();
+            if distance >= min_distance {
+                continue;
+            }
+
+            // Boids in exactly the same position can be pushed in any direction:
+            let normal = offset.try_normalize().unwrap_or(Vec2::X);
+            let push = (min_distance - distance) / 2. * normal;
+            self.boids[i].boid.move_by(-push, self.size);
+            self.boids[j].boid.move_by(push, self.size);
+        }
+    }
+
+    /// Pushes boids that are inside obstacles to the obstacles' surface, and removes the part of
+    /// their velocity that goes into them. Since pushing a boid out of one obstacle may push it into
+    /// another (or through a wall), this is repeated a few times.
+    fn resolve_obstacle_collisions(&mut self) {
+        if self.obstacles.is_empty() {
+            return;
+        }
+
+        for grid_boid in self.boids.iter_mut() {
+            for _ in 0..COLLISION_ITERATIONS {
+                let mut collided = false;
+                for obstacle in self.obstacles.iter() {
+                    collided |=
+                        grid_boid
+                            .boid
+                            .push_out_of(obstacle.pos, obstacle.radius, self.size);
+                }
+                if !collided {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Recalculates the indices of the boids inside the grid.
+    fn recalculate_boid_indices(&mut self) {
+        // For each boid:
+        self.boids
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, grid_boid)| {
+                // Calculate new indices:
+                let (row, col) = cell_of(grid_boid.boid.pos(), self.grid_width, self.grid_height);
+
+                // Boids that stayed in their cell keep their place in it:
+                if (row, col) == (grid_boid.row, grid_boid.col) {
+                    return;
+                }
+
+                // Remove the current index from the outdated grid cell:
+                remove_from_cell(&mut self.location_grid[grid_boid.row][grid_boid.col], i);
+
+                // Update in boid:
+                (grid_boid.row, grid_boid.col) = (row, col);
+
+                // Update in location grid:
+                self.location_grid[row][col].push(i);
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a seeded simulation of the screen's size with the given parameters.
+    fn seeded_sim(seed: u64, config: SimConfig) -> Simulation {
+        let config = SimConfig {
+            seed: Some(seed),
+            ..config
+        };
+        Simulation::new(config, Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT))
+    }
+
+    /// Returns a boid standing still at the given point.
+    fn still_boid(x: f32, y: f32) -> Boid {
+        Boid::new(x, y, Vec2::ZERO, 0)
+    }
+
+    #[test]
+    fn centroid_and_bounding_box_of_known_positions() {
+        let mut sim = seeded_sim(0, SimConfig::default());
+        sim.set_boids([
+            still_boid(100., 100.),
+            still_boid(300., 200.),
+            still_boid(200., 400.),
+        ]);
+
+        assert_eq!(sim.centroid(), Vec2::new(200., 700. / 3.));
+        let bounding_box = sim.bounding_box();
+        assert_eq!(
+            (
+                bounding_box.x,
+                bounding_box.y,
+                bounding_box.w,
+                bounding_box.h
+            ),
+            (100., 100., 200., 300.)
+        );
+    }
+
+    #[test]
+    fn empty_flock_steps_without_panicking() {
+        let mut sim = seeded_sim(1, SimConfig::default());
+        sim.set_leader_idx(Some(0));
+        while !sim.is_empty() {
+            sim.remove_boid(0);
+        }
+        for _ in 0..3 {
+            sim.step();
+        }
+
+        assert!(sim.centroid().is_finite());
+        let bounding_box = sim.bounding_box();
+        assert!([
+            bounding_box.x,
+            bounding_box.y,
+            bounding_box.w,
+            bounding_box.h
+        ]
+        .iter()
+        .all(|value| value.is_finite()));
+        assert!(sim.polarization().is_finite());
+        assert!(sim.group_hulls().is_empty());
+        assert_eq!(sim.nearest_boid(sim.size() / 2., f32::INFINITY), None);
+        assert_eq!(sim.leader_idx(), None);
+    }
+
+    #[test]
+    fn parallel_runs_with_the_same_seed_match() {
+        let config = SimConfig {
+            parallel: true,
+            threads: Some(4),
+            ..SimConfig::default()
+        };
+        let mut first = seeded_sim(7, config.clone());
+        let mut second = seeded_sim(7, config);
+        for _ in 0..200 {
+            first.step();
+            second.step();
+        }
+
+        let bits = |sim: &Simulation| -> Vec<[u32; 2]> {
+            sim.boids()
+                .map(|boid| [boid.pos().x.to_bits(), boid.pos().y.to_bits()])
+                .collect()
+        };
+        assert_eq!(bits(&first), bits(&second));
+    }
+
+    #[test]
+    fn same_species_compares_species_indices() {
+        let mut sim = seeded_sim(0, SimConfig::default());
+        sim.set_boids([
+            Boid::new(100., 100., Vec2::ZERO, 0),
+            Boid::new(200., 100., Vec2::ZERO, 0),
+            Boid::new(300., 100., Vec2::ZERO, 1),
+        ]);
+
+        assert!(sim.same_species(0, 1));
+        assert!(sim.same_species(1, 0));
+        assert!(!sim.same_species(0, 2));
+        assert!(!sim.same_species(2, 1));
+    }
+
+    /// Asserts that every boid's index appears exactly once in the location grid, in the cell the
+    /// boid is marked to be in.
+    fn assert_grid_consistent(sim: &Simulation) {
+        let mut appearances = vec![0; sim.boids.len()];
+        for (row, cells) in sim.location_grid.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                for &idx in cell {
+                    assert!(
+                        idx < sim.boids.len(),
+                        "stale index {idx} in cell ({row}, {col})"
+                    );
+                    assert_eq!(
+                        (sim.boids[idx].row, sim.boids[idx].col),
+                        (row, col),
+                        "boid {idx} is in the wrong cell"
+                    );
+                    appearances[idx] += 1;
+                }
+            }
+        }
+        for (idx, count) in appearances.into_iter().enumerate() {
+            assert_eq!(count, 1, "boid {idx} appears {count} times in the grid");
+        }
+    }
+
+    #[test]
+    fn grid_stays_consistent_through_additions_and_removals() {
+        let config = SimConfig {
+            max_boids: 150,
+            ..SimConfig::default()
+        };
+        let mut sim = seeded_sim(5, config);
+        let mut rng = StdRng::seed_from_u64(5);
+        assert_grid_consistent(&sim);
+        for operation in 0..5000 {
+            if sim.is_empty() || rng.gen_bool(0.5) {
+                let pos = Vec2::new(
+                    rng.gen_range(0. ..sim.size().x),
+                    rng.gen_range(0. ..sim.size().y),
+                );
+                let count = rng.gen_range(1..4);
+                sim.spawn_boids(pos, count, 2. * INFLUENCE_DISTANCE, 0);
+            } else {
+                let idx = rng.gen_range(0..sim.boids.len());
+                sim.remove_boid(idx);
+            }
+            if operation % 250 == 0 {
+                sim.step();
+            }
+            assert_grid_consistent(&sim);
+        }
+    }
+
+    #[test]
+    fn coincident_boids_separate() {
+        let mut sim = seeded_sim(0, SimConfig::default());
+        sim.set_boids([still_boid(400., 300.), still_boid(400., 300.)]);
+        for _ in 0..50 {
+            sim.step();
+            for boid in sim.boids() {
+                assert!(boid.pos().is_finite() && boid.speed().is_finite());
+            }
+        }
+
+        let positions: Vec<Vec2> = sim.boids().map(Boid::pos).collect();
+        assert!(positions[0].distance(positions[1]) > STEERING_DISTANCE);
+    }
+
+    #[test]
+    fn separation_steers_boids_on_a_collision_course_apart() {
+        // Two boids flying at each other in lanes 10 units apart:
+        let mut sim = seeded_sim(0, SimConfig::default());
+        sim.set_boids([still_boid(350., 295.), still_boid(450., 305.)]);
+        sim.set_boid_velocity(0, Vec2::new(MAX_BOID_VELOCITY, 0.));
+        sim.set_boid_velocity(1, Vec2::new(-MAX_BOID_VELOCITY, 0.));
+
+        let mut closest = f32::INFINITY;
+        for _ in 0..20 {
+            sim.step();
+            let boids: Vec<&Boid> = sim.boids().collect();
+            closest = closest.min(boids[0].pos().distance(boids[1].pos()));
+        }
+
+        // They swerve away from each other instead of passing at their lanes' distance:
+        let boids: Vec<&Boid> = sim.boids().collect();
+        assert!(closest > 10., "closest distance {closest}");
+        assert!(boids[0].pos().y < 295. && boids[0].speed().y < 0.);
+        assert!(boids[1].pos().y > 305. && boids[1].speed().y > 0.);
+    }
+
+    #[test]
+    fn rule_contributions_follow_their_boids() {
+        let mut sim = seeded_sim(0, SimConfig::default());
+        sim.set_boids([
+            still_boid(400., 300.),
+            still_boid(410., 300.),
+            still_boid(400., 310.),
+        ]);
+        assert!(sim.last_rule_contributions(0).is_none());
+
+        sim.step();
+        let last = sim.last_rule_contributions(2).unwrap();
+        assert!(last[0].length() > 0., "separation gave no direction");
+        sim.remove_boid(0);
+        assert_eq!(sim.last_rule_contributions(0), Some(last));
+        assert!(sim.last_rule_contributions(2).is_none());
+    }
+
+    #[test]
+    fn nearest_boid_breaks_ties_by_id() {
+        // The point is on a cell boundary, and the boid scanned first (in the left cell) has the
+        // higher id:
+        let mut sim = seeded_sim(0, SimConfig::default());
+        let point = Vec2::new(3. * INFLUENCE_DISTANCE, 300.);
+        let (right, left) = (point + Vec2::X * 5., point - Vec2::X * 5.);
+        sim.set_boids([
+            still_boid(100., 100.),
+            still_boid(right.x, right.y),
+            still_boid(left.x, left.y),
+        ]);
+        assert_eq!(sim.nearest_boid(point, INFLUENCE_DISTANCE), Some(1));
+        let first_id = sim.boids[1].id;
+
+        // Removing the first boid moves the last one before it, so the indices no longer follow
+        // the ids:
+        sim.remove_boid(0);
+        assert_eq!(sim.boids[0].boid.pos(), left);
+        let nearest = sim.nearest_boid(point, INFLUENCE_DISTANCE).unwrap();
+        assert_eq!(nearest, 1);
+        assert_eq!(sim.boids[nearest].id, first_id);
+    }
+
+    #[test]
+    fn boids_on_cell_boundaries_have_exactly_one_cell() {
+        // An area of whole cells, so its far edges are cell boundaries too:
+        let (columns, rows) = (10, 8);
+        let size = INFLUENCE_DISTANCE * Vec2::new(columns as f32, rows as f32);
+        let config = SimConfig {
+            seed: Some(0),
+            ..SimConfig::default()
+        };
+        let mut sim = Simulation::new(config, size);
+        assert_eq!((sim.grid_width, sim.grid_height), (columns + 1, rows + 1));
+
+        // Sweep a boid over every multiple of the influence distance from the near edges to a cell
+        // past the far edges, and over points outside the area, which are clamped into the grid:
+        let boundaries = |count: usize| (0..=count + 1).map(|i| (i as f32 * INFLUENCE_DISTANCE, i));
+        let clamped = |count: usize| {
+            [
+                (-INFLUENCE_DISTANCE, 0),
+                (-0.5, 0),
+                (f32::NAN, 0),
+                ((count + 5) as f32 * INFLUENCE_DISTANCE, count),
+            ]
+        };
+        let xs: Vec<(f32, usize)> = boundaries(columns).chain(clamped(columns)).collect();
+        let ys: Vec<(f32, usize)> = boundaries(rows).chain(clamped(rows)).collect();
+        for &(y, row) in ys.iter() {
+            for &(x, col) in xs.iter() {
+                sim.boids[0].boid = still_boid(x, y);
+                sim.recalculate_boid_indices();
+                assert_grid_consistent(&sim);
+                let expected = (row.min(rows), col.min(columns));
+                assert_eq!(
+                    (sim.boids[0].row, sim.boids[0].col),
+                    expected,
+                    "at ({x}, {y})"
+                );
+                if x.is_finite() && y.is_finite() {
+                    assert!(sim.boids_in_radius(Vec2::new(x, y), 0.).contains(&0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn walls_add_to_obstacle_evasion() {
+        // A boid in the top margin, right above an obstacle by the wall:
+        let mut sim = seeded_sim(0, SimConfig::default());
+        sim.set_boids([Boid::new(400., 20., Vec2::new(MAX_BOID_VELOCITY, 0.), 0)]);
+        let walls_only = sim.calc_evasion_directions()[0];
+        assert!(walls_only.y > 0., "the top wall didn't push the boid down");
+
+        sim.add_obstacle(Obstacle::new(Vec2::new(400., 40.), 10.));
+        let evasion = sim.calc_evasion_directions()[0];
+        assert_eq!(evasion - walls_only, Vec2::new(0., -EVASION_FACTOR));
+    }
+
+    #[test]
+    fn walls_add_to_boundary_evasion() {
+        // A boid in the left margin, where the boundary's edge runs along the screen's edge:
+        let boid = Boid::new(20., 500., Vec2::new(0., MAX_BOID_VELOCITY), 0);
+        let mut walls_only = seeded_sim(0, SimConfig::default());
+        walls_only.set_boids([boid.clone()]);
+        let screen = [
+            [0., 0.],
+            [SCREEN_WIDTH, 0.],
+            [SCREEN_WIDTH, SCREEN_HEIGHT],
+            [0., SCREEN_HEIGHT],
+        ];
+        let config = SimConfig {
+            boundary_polygon: Some(screen.to_vec()),
+            ..SimConfig::default()
+        };
+        let mut bounded = seeded_sim(0, config);
+        bounded.set_boids([boid]);
+
+        let walls = walls_only.calc_evasion_directions()[0];
+        assert!(walls.x > 0., "the left wall didn't push the boid right");
+        let evasion = bounded.calc_evasion_directions()[0];
+        assert!((evasion - walls).distance(Vec2::new(EVASION_FACTOR, 0.)) < 1e-5);
+    }
+
+    #[test]
+    fn touching_groups_merge_while_boids_are_removed() {
+        let config = SimConfig {
+            merge_on_contact: true,
+            merge_contact_steps: 10,
+            ..SimConfig::default()
+        };
+        let mut sim = seeded_sim(0, config);
+        // Two touching pairs of different species, one at the start and one at the end (so the
+        // removals move its boids), with boids of another species far from both in between:
+        let fillers = (0..20).map(|i| Boid::new(100. + 60. * i as f32, 800., Vec2::ZERO, 2));
+        sim.set_boids(
+            [still_boid(400., 300.), Boid::new(410., 300., Vec2::ZERO, 1)]
+                .into_iter()
+                .chain(fillers)
+                .chain([still_boid(900., 300.), Boid::new(910., 300., Vec2::ZERO, 1)]),
+        );
+
+        for _ in 0..10 {
+            sim.merge_touching_groups();
+            let filler = sim.boids().position(|boid| boid.species() == 2).unwrap();
+            sim.remove_boid(filler);
+            assert_grid_consistent(&sim);
+        }
+
+        let species_at = |pos: Vec2| {
+            let idx = sim.nearest_boid(pos, 1.).unwrap();
+            sim.boids[idx].boid.species()
+        };
+        let first = (
+            species_at(Vec2::new(400., 300.)),
+            species_at(Vec2::new(410., 300.)),
+        );
+        let last = (
+            species_at(Vec2::new(900., 300.)),
+            species_at(Vec2::new(910., 300.)),
+        );
+        assert_eq!(first.0, first.1, "the first pair didn't merge");
+        assert_eq!(last.0, last.1, "the last pair didn't merge");
+    }
+
+    /// Returns the indices of the boids up to `radius` away from `center` by checking every boid.
+    fn brute_force_in_radius(sim: &Simulation, center: Vec2, radius: f32) -> Vec<usize> {
+        sim.boids()
+            .enumerate()
+            .filter(|(_, boid)| boid.pos().distance_squared(center) <= radius * radius)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    #[test]
+    fn boids_in_radius_matches_brute_force() {
+        let mut sim = seeded_sim(3, SimConfig::default());
+        for _ in 0..50 {
+            sim.step();
+        }
+        let size = sim.size();
+        let corners = [
+            Vec2::ZERO,
+            Vec2::new(size.x, 0.),
+            Vec2::new(0., size.y),
+            size,
+        ];
+        let centers = corners.into_iter().chain([size / 2.]);
+        for center in centers {
+            for radius in [
+                INFLUENCE_DISTANCE / 2.,
+                INFLUENCE_DISTANCE,
+                INFLUENCE_DISTANCE * 3.5,
+                size.max_element(),
+            ] {
+                let mut found = sim.boids_in_radius(center, radius);
+                found.sort_unstable();
+                assert_eq!(
+                    found,
+                    brute_force_in_radius(&sim, center, radius),
+                    "center {center}, radius {radius}"
+                );
+            }
+        }
+    }
+}
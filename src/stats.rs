@@ -0,0 +1,156 @@
+use crate::simulation::Simulation;
+use ggez::glam::Vec2;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Statistics about the structure of the flock, i.e. how the boids are split into groups.
+pub struct GroupStats {
+    // Number of groups:
+    pub groups: usize,
+    // Number of boids in the largest group:
+    pub largest_group: usize,
+    // Average number of boids in a group:
+    pub mean_group_size: f32,
+}
+
+impl GroupStats {
+    /// Calculates the statistics given the representative of every boid's group. Without boids,
+    /// all statistics are zero.
+    pub fn from_representatives(representatives: &[usize]) -> Self {
+        let mut sizes = vec![0usize; representatives.len()];
+        for &representative in representatives {
+            sizes[representative] += 1;
+        }
+
+        let groups = sizes.iter().filter(|&&size| size > 0).count();
+        Self {
+            groups,
+            largest_group: sizes.iter().copied().max().unwrap_or(0),
+            mean_group_size: if groups == 0 {
+                0.
+            } else {
+                representatives.len() as f32 / groups as f32
+            },
+        }
+    }
+}
+
+/// Number of rules steering the boids (including the noise).
+pub const RULE_COUNT: usize = 12;
+
+/// What steered a single traced boid in a step.
+#[derive(Clone, Copy)]
+pub struct RuleTrace {
+    // Number of other boids up to the influence distance away:
+    pub neighbors: usize,
+    // The name of every rule and the direction it gave the boid (before calm zones and the
+    // maximal force), in the order the rules are calculated:
+    pub contributions: [(&'static str, Vec2); RULE_COUNT],
+}
+
+/// What the location grid holds in a single cell, for debugging the neighbor search.
+pub struct CellInspection {
+    // The cell's row and column:
+    pub row: usize,
+    pub col: usize,
+    // Indices of the boids in the cell, in the order they're scanned:
+    pub boids: Vec<usize>,
+    // The rows and columns of the cells scanned for the close boids of a boid in the cell
+    // (including the cell itself, see NEIGHBOR_RINGS):
+    pub neighborhood: Vec<(usize, usize)>,
+}
+
+impl RuleTrace {
+    /// Returns the name of the rule whose direction was the longest.
+    pub fn dominant_rule(&self) -> &'static str {
+        self.contributions
+            .iter()
+            .max_by(|(_, a), (_, b)| a.length_squared().total_cmp(&b.length_squared()))
+            .map_or("none", |&(name, _)| name)
+    }
+}
+
+/// Records statistics of simulations over time to a CSV file, one row per simulation in each
+/// record.
+pub struct StatsRecorder {
+    file: BufWriter<File>,
+}
+
+impl StatsRecorder {
+    /// Creates the CSV file in the given path and writes its header.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "step,time,region,boids,polarization,groups,largest_group,mean_group_size"
+        )?;
+        Ok(Self { file })
+    }
+
+    /// Writes the current statistics of the simulation with the given region index.
+    pub fn record(&mut self, region: usize, sim: &Simulation) -> std::io::Result<()> {
+        let stats = sim.group_stats();
+        writeln!(
+            self.file,
+            "{},{:.4},{},{},{},{},{},{}",
+            sim.step_count(),
+            sim.time(),
+            region,
+            sim.iter_boids().count(),
+            sim.polarization(),
+            stats.groups,
+            stats.largest_group,
+            stats.mean_group_size
+        )
+    }
+
+    /// Writes everything recorded so far to the file.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Records the trajectory of a single boid to a CSV file, one row per step. This is much lighter
+/// than recording the whole flock, and shows which rule steered the boid in every step.
+pub struct TraceRecorder {
+    file: BufWriter<File>,
+}
+
+impl TraceRecorder {
+    /// Creates the CSV file in the given path and writes its header.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "step,time,id,x,y,velocity_x,velocity_y,neighbors,dominant_rule"
+        )?;
+        Ok(Self { file })
+    }
+
+    /// Writes the traced boid's current state, if the simulation traces a boid and has stepped
+    /// since it started.
+    pub fn record(&mut self, sim: &Simulation) -> std::io::Result<()> {
+        let Some((boid, trace)) = sim.traced_boid() else {
+            return Ok(());
+        };
+        writeln!(
+            self.file,
+            "{},{:.4},{},{},{},{},{},{},{}",
+            sim.step_count(),
+            sim.time(),
+            boid.id,
+            boid.pos.x,
+            boid.pos.y,
+            boid.velocity.x,
+            boid.velocity.y,
+            trace.neighbors,
+            trace.dominant_rule()
+        )
+    }
+
+    /// Writes everything recorded so far to the file.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
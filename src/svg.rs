@@ -0,0 +1,142 @@
+use crate::boid::Boid;
+use crate::color::Rgba;
+use crate::config::LeaderMode;
+use crate::constants::{BOID_COLORS, PREDATOR_SCALE};
+use crate::simulation::Simulation;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Converts a color to an SVG fill/stroke value and its opacity.
+fn svg_color(color: Rgba) -> (String, f32) {
+    let channel = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    (
+        format!(
+            "rgb({}, {}, {})",
+            channel(color.r),
+            channel(color.g),
+            channel(color.b)
+        ),
+        color.a,
+    )
+}
+
+/// Converts the current state of the simulation to an SVG image, drawn the same way it is drawn on
+/// the screen (without the camera).
+pub fn to_svg(sim: &Simulation) -> String {
+    let mut svg = String::new();
+    let (width, height) = (sim.size().x, sim.size().y);
+
+    // Writing to a string can't fail, so the results are ignored:
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    // Draw the boundary, the reefs, the calm zones, the obstacles and the leaders' circles below the boids:
+    if let Some(boundary) = sim.boundary() {
+        let points: Vec<String> = boundary
+            .points
+            .iter()
+            .map(|point| format!("{},{}", point.x, point.y))
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{}" fill="none" stroke="rgb(128, 128, 128)" stroke-width="2"/>"#,
+            points.join(" ")
+        );
+    }
+    for reef in sim.reefs() {
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="rgb(0, 204, 0)" fill-opacity="0.15"/>"#,
+            reef.pos.x, reef.pos.y, reef.radius
+        );
+    }
+    for calm_zone in sim.calm_zones() {
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="rgb(0, 0, 255)" fill-opacity="0.15"/>"#,
+            calm_zone.pos.x, calm_zone.pos.y, calm_zone.radius
+        );
+    }
+    for obstacle in sim.obstacles() {
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="rgb(128, 128, 128)"/>"#,
+            obstacle.pos.x, obstacle.pos.y, obstacle.radius
+        );
+    }
+    let leaders: Vec<(&Boid, Rgba)> = match sim.config().leader_mode {
+        LeaderMode::Single => sim
+            .leader()
+            .map(|leader| (leader, Rgba::YELLOW))
+            .into_iter()
+            .collect(),
+        LeaderMode::PerGroup => sim
+            .group_leaders()
+            .map(|leader| (leader, leader.color()))
+            .collect(),
+    };
+    for (leader, color) in leaders {
+        let color = if sim.leader_repels() {
+            Rgba::RED
+        } else {
+            color
+        };
+        let (stroke, opacity) = svg_color(color);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="30" fill="none" stroke="{stroke}" stroke-opacity="{opacity}" stroke-width="5"/>"#,
+            leader.pos().x,
+            leader.pos().y
+        );
+    }
+
+    // Draw every boid as its shape, moved and rotated like on the screen (with its identifier, so
+    // boids can be matched between images):
+    let points: Vec<String> = Boid::get_boid_shape()
+        .iter()
+        .map(|point| format!("{},{}", point.x, point.y))
+        .collect();
+    let points = points.join(" ");
+    for boid in sim.iter_boids() {
+        let (fill, opacity) = svg_color(BOID_COLORS[boid.species_index]);
+        let _ = writeln!(
+            svg,
+            r#"<polygon data-id="{}" points="{points}" fill="{fill}" fill-opacity="{opacity}" transform="translate({} {}) rotate({})"/>"#,
+            boid.id,
+            boid.pos.x,
+            boid.pos.y,
+            boid.heading.to_degrees()
+        );
+    }
+
+    // Draw the predators like the boids, but larger and in red:
+    for predator in sim.predators() {
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{points}" fill="rgb(255, 0, 0)" transform="translate({} {}) rotate({}) scale({PREDATOR_SCALE})"/>"#,
+            predator.pos().x,
+            predator.pos().y,
+            predator.rotation().to_degrees()
+        );
+    }
+
+    // Draw the target above the boids:
+    if let Some(target) = sim.target() {
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="10" fill="rgb(255, 0, 0)"/>"#,
+            target.x, target.y
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes the current state of the simulation as an SVG image to the given path.
+pub fn export_svg(sim: &Simulation, path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, to_svg(sim))
+}
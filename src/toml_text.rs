@@ -0,0 +1,390 @@
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+/// An error writing a value as TOML text, naming the parameter it happened in (if any).
+#[derive(Debug)]
+pub struct TomlTextError(String);
+
+impl fmt::Display for TomlTextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TomlTextError {}
+
+impl ser::Error for TomlTextError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// A value written as TOML text. Tables keep their entries apart, so the outermost table can be
+/// written a key per line and the tables inside it inline.
+enum Text {
+    Inline(String),
+    Table(Vec<(String, Text)>),
+}
+
+impl Text {
+    /// Returns the value written inline (tables as inline tables).
+    fn inline(self) -> String {
+        match self {
+            Text::Inline(text) => text,
+            Text::Table(entries) => {
+                let entries: Vec<String> = entries
+                    .into_iter()
+                    .map(|(key, value)| format!("{key} = {}", value.inline()))
+                    .collect();
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+    }
+
+    /// Returns a table holding the given value under the name of an enum's variant, the way serde
+    /// reads enum variants holding values back.
+    fn variant(variant: &str, value: Option<Text>) -> Text {
+        Text::Table(
+            value
+                .map(|value| (variant.to_string(), value))
+                .into_iter()
+                .collect(),
+        )
+    }
+}
+
+/// Returns the given value (e.g: a struct) as the text of a TOML file, a `key = value` line per
+/// entry sorted by key, with any tables inside it written inline. TOML has no null, so values that
+/// aren't given (e.g: `None`) have no line.
+/// Unlike TOML's own serializer, this writes enum variants holding values (as tables holding the
+/// value under the variant's name), so any parameters `toml::from_str` reads can be written.
+pub fn to_toml_lines<T: Serialize>(value: &T) -> Result<String, TomlTextError> {
+    let Some(Text::Table(mut entries)) = value.serialize(TextSerializer)? else {
+        return Err(TomlTextError(
+            "only tables can be written as a TOML file".into(),
+        ));
+    };
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(entries
+        .into_iter()
+        .map(|(key, value)| format!("{key} = {}\n", value.inline()))
+        .collect())
+}
+
+/// Returns the TOML text of a string.
+fn string(value: &str) -> Text {
+    Text::Inline(toml::Value::String(value.to_string()).to_string())
+}
+
+/// Returns the TOML text of a float. TOML writes non-finite floats as `inf`, `-inf` and `nan`.
+fn float<F: Into<f64> + fmt::Debug + Copy>(value: F) -> Text {
+    let wide: f64 = value.into();
+    Text::Inline(if wide.is_nan() {
+        "nan".into()
+    } else if wide.is_infinite() {
+        if wide > 0. { "inf" } else { "-inf" }.into()
+    } else {
+        format!("{value:?}")
+    })
+}
+
+/// Writes values as TOML text (`None` for values that aren't given).
+struct TextSerializer;
+
+impl ser::Serializer for TextSerializer {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    type SerializeSeq = SeqText;
+    type SerializeTuple = SeqText;
+    type SerializeTupleStruct = SeqText;
+    type SerializeTupleVariant = SeqText;
+    type SerializeMap = TableText;
+    type SerializeStruct = TableText;
+    type SerializeStructVariant = TableText;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Text::Inline(v.to_string())))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Text::Inline(v.to_string())))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        // TOML integers are 64 bit signed integers:
+        let v = i64::try_from(v)
+            .map_err(|_| TomlTextError(format!("{v} is too large for a TOML integer")))?;
+        self.serialize_i64(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(float(v)))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(float(v)))
+    }
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(string(&v.to_string())))
+    }
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(string(v)))
+    }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(TomlTextError("bytes can't be written as TOML".into()))
+    }
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(string(variant)))
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Text::variant(variant, value.serialize(self)?)))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SeqText {
+            values: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SeqText {
+            values: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(TableText::default())
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(TableText::default())
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(TableText {
+            variant: Some(variant),
+            ..TableText::default()
+        })
+    }
+}
+
+/// Writes an array (of a sequence, a tuple or an enum variant holding a tuple) as TOML text.
+struct SeqText {
+    values: Vec<String>,
+    variant: Option<&'static str>,
+}
+
+impl SeqText {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), TomlTextError> {
+        if let Some(value) = value.serialize(TextSerializer)? {
+            self.values.push(value.inline());
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Text>, TomlTextError> {
+        let array = Text::Inline(format!("[{}]", self.values.join(", ")));
+        Ok(Some(match self.variant {
+            Some(variant) => Text::variant(variant, Some(array)),
+            None => array,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SeqText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SeqText::end(self)
+    }
+}
+
+impl ser::SerializeTuple for SeqText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SeqText::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SeqText::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SeqText::end(self)
+    }
+}
+
+/// Writes a table (of a map, a struct or an enum variant holding a struct) as TOML text.
+#[derive(Default)]
+struct TableText {
+    entries: Vec<(String, Text)>,
+    // The key of the map entry whose value comes next:
+    key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl TableText {
+    fn insert<T: ?Sized + Serialize>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), TomlTextError> {
+        // Name the parameter the error happened in, so it can be found in the configuration:
+        let value = value
+            .serialize(TextSerializer)
+            .map_err(|err| TomlTextError(format!("{key}: {err}")))?;
+        if let Some(value) = value {
+            self.entries.push((key, value));
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Text>, TomlTextError> {
+        let table = Text::Table(self.entries);
+        Ok(Some(match self.variant {
+            Some(variant) => Text::variant(variant, Some(table)),
+            None => table,
+        }))
+    }
+}
+
+impl ser::SerializeMap for TableText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        // Written keys are valid TOML keys as they are (strings are quoted, numbers are bare):
+        let key = key
+            .serialize(TextSerializer)?
+            .ok_or_else(|| TomlTextError("map keys must be given".into()))?;
+        self.key = Some(key.inline());
+        Ok(())
+    }
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .expect("serde serializes a map's key before its value");
+        self.insert(key, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        TableText::end(self)
+    }
+}
+
+impl ser::SerializeStruct for TableText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        TableText::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for TableText {
+    type Ok = Option<Text>;
+    type Error = TomlTextError;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        TableText::end(self)
+    }
+}